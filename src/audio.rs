@@ -1,131 +1,99 @@
 use std::sync::{Arc, RwLock};
 
-use pipewire::{stream::*, properties, spa::{Direction, pod::{deserialize::PodDeserializer, Value}, utils::Id}, MainLoop};
+use crate::visualiser::BufferManager;
 
-use crate::{audio::pod_choice_default::Fixate, visualiser::BufferManager};
+#[cfg(feature = "pipewire")]
+pub(crate) use self::pipewire::{find_node, list_nodes, PipeWireSource, TargetSender};
+pub(crate) use self::file::{FileOptions, FileReader, FileSource};
+pub(crate) use self::pcm::{PcmFormat, PcmReader, PcmSource};
+pub(crate) use self::tone::Tone;
 
-mod spa_audio_info_raw;
-mod pod_choice_default;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+mod file;
+mod pcm;
+mod sample_format;
+mod tone;
 
-#[derive(Debug)]
-struct StreamConfiguration {
-	rate: u32,
-	channels: u32,
-	format: u32,
+/// The shape of the samples a source is delivering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AudioFormat {
+	pub rate: u32,
+	pub channels: u32,
 }
 
-#[derive(Default)]
-struct StreamData {
-	configuration: Option<StreamConfiguration>,
-	visualiser: Arc<RwLock<BufferManager>>,
+/// What a source is doing, which the idle animation shows whenever it isn't
+/// streaming. The value is `audio_state` in the shaders' `FrameInfo`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SourceState {
+	/// Samples are arriving, even if they are silent
+	Streaming = 0,
+	/// Connected but nothing is arriving, e.g. a paused stream or a file
+	/// which has ended
+	Idle = 1,
+	/// Waiting to connect or for the first samples
+	#[default]
+	Connecting = 2,
+	/// The connection was lost and will be retried
+	// only PipeWire has a connection to lose
+	#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+	Disconnected = 3,
 }
 
-pub(crate) fn main(
-	visualiser: Arc<RwLock<BufferManager>>,
-) {
-	std::thread::spawn(move || {
-		let mainloop = MainLoop::new().unwrap();
-		let _stream = stream(&mainloop, visualiser);
-		mainloop.run();
-	});
+/// Somewhere audio can be captured from, which feeds its samples to an
+/// [`AudioSink`] from a thread of its own once started.
+pub(crate) trait AudioSource {
+	fn start(&mut self, sink: AudioSink) -> std::io::Result<()>;
+	/// Stops delivering samples, only returning once no more will arrive.
+	fn stop(&mut self);
+	/// The format of the samples being delivered, once it is known.
+	// part of what a source reports, though nothing shows it at the moment
+	#[allow(dead_code)]
+	fn format(&self) -> Option<AudioFormat>;
+}
+
+/// Audio which can be read as quickly as it is asked for, so whatever is
+/// made of it comes out the same however long that takes.
+pub(crate) trait AudioReader {
+	/// Reads up to `frames` frames as a block for each channel, along with
+	/// their format. Returns `None` once there is nothing left.
+	fn read(&mut self, frames: usize) -> std::io::Result<Option<(AudioFormat, Vec<Vec<f32>>)>>;
 }
 
-fn stream(
-	mainloop: &MainLoop,
+/// Where a source delivers its samples, so every source feeds the visualiser
+/// the same way.
+#[derive(Clone)]
+pub(crate) struct AudioSink {
 	visualiser: Arc<RwLock<BufferManager>>,
-) -> Stream<StreamData> {
-	let stream = Stream::<StreamData>::with_user_data(
-		mainloop,
-		"audio-capture",
-		properties! {
-			*pipewire::keys::NODE_NAME => env!("CARGO_PKG_NAME"),
-			*pipewire::keys::MEDIA_TYPE => "Audio",
-			*pipewire::keys::MEDIA_CATEGORY => "Capture",
-			*pipewire::keys::STREAM_CAPTURE_SINK => "true",
-		},
-		StreamData {
-			configuration: None,
-			visualiser,
-		},
-	)
-	.param_changed(|id, data, raw_pod| {
-		if id == libspa_sys::SPA_PARAM_Format {
-			let pointer = std::ptr::NonNull::new(raw_pod.cast_mut()).unwrap();
-			let object = unsafe {
-				PodDeserializer::deserialize_ptr::<Value>(pointer).unwrap()
-			};
+}
 
-			if let Value::Object(object) = object {
-				data.configuration = None;
+impl AudioSink {
+	pub fn new(visualiser: Arc<RwLock<BufferManager>>) -> Self {
+		Self { visualiser }
+	}
 
-				let media_type: Id = object.properties.iter()
-					.find(|p| p.key == libspa_sys::SPA_FORMAT_mediaType)
-					.unwrap().value
-					.fixate().unwrap();
-				
-				let media_subtype: Id = object.properties.iter()
-					.find(|p| p.key == libspa_sys::SPA_FORMAT_mediaSubtype)
-					.unwrap().value
-					.fixate().unwrap();
-				
-				let format: Id = object.properties.iter()
-					.find(|p| p.key == libspa_sys::SPA_FORMAT_AUDIO_format)
-					.unwrap().value
-					.fixate().unwrap();
-				
-				let rate: i32 = object.properties.iter()
-					.find(|p| p.key == libspa_sys::SPA_FORMAT_AUDIO_rate)
-					.unwrap().value
-					.fixate().unwrap();
-				
-				let channels: i32 = object.properties.iter()
-					.find(|p| p.key == libspa_sys::SPA_FORMAT_AUDIO_channels)
-					.unwrap().value
-					.fixate().unwrap();
-				
-				let is_audio = media_type.0 == libspa_sys::SPA_MEDIA_TYPE_audio;
-				let is_raw = media_subtype.0 == libspa_sys::SPA_MEDIA_SUBTYPE_raw;
-				if is_audio && is_raw {
-					data.configuration = Some(StreamConfiguration {
-						rate: rate as u32,
-						channels: channels as u32,
-						format: format.0,
-					});
-				}
-			}
-		}
-	})
-	.process(|stream, StreamData { configuration, visualiser }| {
-		if let Some(mut buffer) = stream.dequeue_buffer() {
-			// TODO: this is just the left channel — maybe handle all channels
-			let channel = buffer.datas_mut().get_mut(0).unwrap();
-			let offset = channel.chunk().offset();
-			let chunk = channel.chunk(); 
-			let size = chunk.size() as usize;
-			let stride = chunk.stride();
-			let data = channel.data(); 
+	/// Delivers a block of samples at `rate`, with a slice for each channel.
+	/// The source counts as streaming from then on.
+	pub fn deliver(&self, channels: &[&[f32]], rate: u32) {
+		let mut visualiser = self.visualiser.write().unwrap();
 
-			if let Some(data) = data {
-				let rate = configuration.as_ref().unwrap().rate;
+		visualiser.set_source_state(SourceState::Streaming);
+		visualiser.fill_buffer(channels, rate);
+	}
 
-				let cast_buffer: &[f32] = unsafe {
-					std::slice::from_raw_parts(data.as_ptr().cast(), size / std::mem::size_of::<f32>())
-				};
-				visualiser.write().unwrap()
-					.fill_buffer(cast_buffer, rate)
-			}
-		}
-	})
-	.create().unwrap();
+	/// Reports why samples have stopped arriving.
+	pub fn set_state(&self, state: SourceState) {
+		self.visualiser.write().unwrap().set_source_state(state);
+	}
+}
 
-	let params = spa_audio_info_raw::SpaAudioInfoRaw::empty().as_pod().unwrap();
+/// Without PipeWire there is never a capture to move to another target.
+#[cfg(not(feature = "pipewire"))]
+pub(crate) enum TargetSender {}
 
-	stream.connect(
-		Direction::Input,
-		None,
-		StreamFlags::AUTOCONNECT | StreamFlags::RT_PROCESS | StreamFlags::MAP_BUFFERS,
-		&mut [params.as_ptr().cast()],
-	).unwrap();
-	
-	stream
+#[cfg(not(feature = "pipewire"))]
+impl TargetSender {
+	pub fn send(&self, _target: Option<&str>) {
+		match *self {}
+	}
 }
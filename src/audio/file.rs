@@ -0,0 +1,449 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+use super::{AudioFormat, AudioReader, AudioSink, AudioSource, SourceState};
+#[cfg(feature = "pipewire")]
+use super::pipewire::Playback;
+
+/// Most frames delivered at once, so the file arrives as smoothly as a live
+/// stream would rather than a whole packet at a time.
+const BLOCK_FRAMES: usize = 256;
+
+#[derive(Debug)]
+pub(crate) enum FileError {
+	Decode(SymphoniaError),
+	NoAudioTrack,
+}
+
+impl fmt::Display for FileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FileError::Decode(error) => write!(f, "{}", error),
+			FileError::NoAudioTrack => write!(f, "no audio track"),
+		}
+	}
+}
+
+impl From<SymphoniaError> for FileError {
+	fn from(error: SymphoniaError) -> Self {
+		FileError::Decode(error)
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FileOptions {
+	/// where in the file to start
+	pub start: Duration,
+	/// start from the beginning again at the end of the file
+	pub looping: bool,
+	/// play the file through the default output as well
+	pub play: bool,
+}
+
+/// Decodes an audio file and delivers it in real time, paced by the wall
+/// clock.
+pub(crate) struct FileSource {
+	path: PathBuf,
+	options: FileOptions,
+	format: Arc<Mutex<Option<AudioFormat>>>,
+	running: Option<Running>,
+}
+
+struct Running {
+	stop: Arc<AtomicBool>,
+	thread: JoinHandle<()>,
+}
+
+impl FileSource {
+	/// Checks the file can be decoded, so problems are reported before
+	/// anything starts.
+	pub fn open(path: &Path, options: FileOptions) -> Result<Self, FileError> {
+		FileDecoder::open(path)?;
+
+		Ok(Self {
+			path: path.to_owned(),
+			options,
+			format: Arc::new(Mutex::new(None)),
+			running: None,
+		})
+	}
+}
+
+impl AudioSource for FileSource {
+	fn start(&mut self, sink: AudioSink) -> io::Result<()> {
+		if self.running.is_some() {
+			return Ok(());
+		}
+
+		let stop = Arc::new(AtomicBool::new(false));
+
+		let thread = std::thread::Builder::new()
+			.name("file".to_owned())
+			.spawn({
+				let path = self.path.clone();
+				let options = self.options;
+				let format = Arc::clone(&self.format);
+				let stop = Arc::clone(&stop);
+				move || {
+					if let Err(error) = play(&path, options, &sink, &format, &stop) {
+						eprintln!("Failed to decode {}: {}", path.display(), error);
+					}
+
+					sink.set_state(SourceState::Idle);
+				}
+			})?;
+
+		self.running = Some(Running { stop, thread });
+
+		Ok(())
+	}
+
+	fn stop(&mut self) {
+		let Some(Running { stop, thread }) = self.running.take() else {
+			return;
+		};
+
+		stop.store(true, Ordering::Relaxed);
+
+		if thread.join().is_err() {
+			eprintln!("File decoding thread panicked");
+		}
+
+		*self.format.lock().unwrap() = None;
+	}
+
+	fn format(&self) -> Option<AudioFormat> {
+		*self.format.lock().unwrap()
+	}
+}
+
+/// Delivers the file to `sink` until it ends or `stop` is set.
+fn play(
+	path: &Path,
+	options: FileOptions,
+	sink: &AudioSink,
+	format: &Mutex<Option<AudioFormat>>,
+	stop: &AtomicBool,
+) -> Result<(), FileError> {
+	let mut reader = FileReader::open(path, options)?;
+
+	#[cfg(feature = "pipewire")]
+	let mut playback: Option<Playback> = None;
+
+	#[cfg(not(feature = "pipewire"))]
+	if options.play {
+		eprintln!("Built without PipeWire support, so the file can't be played");
+	}
+
+	// the time each block is due is counted from here, so that sleeping
+	// too long for one block is made up for by the next
+	let started = Instant::now();
+	let mut delivered = Duration::ZERO;
+
+	while !stop.load(Ordering::Relaxed) {
+		let Some((block_format, channels)) = reader.read_frames(BLOCK_FRAMES)? else {
+			break;
+		};
+
+		*format.lock().unwrap() = Some(block_format);
+
+		#[cfg(feature = "pipewire")]
+		if options.play && playback.as_ref().map(Playback::format) != Some(block_format) {
+			// replaced rather than kept, a stream can't change its format
+			drop(playback.take());
+			playback = Playback::start(block_format)
+				.map_err(|error| eprintln!("Failed to start playback: {}", error))
+				.ok();
+		}
+
+		let frames = channels.first().map_or(0, Vec::len);
+		let block = channels.iter()
+			.map(Vec::as_slice)
+			.collect::<Vec<_>>();
+
+		if let Some(wait) = (started + delivered).checked_duration_since(Instant::now()) {
+			std::thread::sleep(wait);
+		}
+
+		#[cfg(feature = "pipewire")]
+		if let Some(playback) = &playback {
+			playback.queue(&block);
+		}
+
+		sink.deliver(&block, block_format.rate);
+		delivered += Duration::from_secs_f64(frames as f64 / block_format.rate as f64);
+	}
+
+	Ok(())
+}
+
+/// Decodes a file as quickly as it is read from, rather than in real time.
+pub(crate) struct FileReader {
+	path: PathBuf,
+	looping: bool,
+	decoder: FileDecoder,
+	format: AudioFormat,
+	/// the latest decoded packet, a block for each channel
+	pending: Vec<Vec<f32>>,
+	/// frames of `pending` already read
+	position: usize,
+	/// stops an empty file from being reopened forever when looping
+	decoded: bool,
+}
+
+impl FileReader {
+	pub fn open(path: &Path, options: FileOptions) -> Result<Self, FileError> {
+		let mut decoder = FileDecoder::open(path)?;
+		decoder.seek(options.start)?;
+
+		Ok(Self {
+			path: path.to_owned(),
+			looping: options.looping,
+			decoder,
+			format: AudioFormat { rate: 0, channels: 0 },
+			pending: Vec::new(),
+			position: 0,
+			decoded: false,
+		})
+	}
+
+	/// Reads up to `frames` frames, fewer if a packet ends first, with a
+	/// block for each channel. Returns `None` at the end of the file.
+	pub fn read_frames(&mut self, frames: usize) -> Result<Option<(AudioFormat, Vec<Vec<f32>>)>, FileError> {
+		while self.position >= self.pending.first().map_or(0, Vec::len) {
+			let Some((format, samples)) = self.decoder.next_block()? else {
+				if !self.looping || !self.decoded {
+					return Ok(None);
+				}
+
+				self.decoder = FileDecoder::open(&self.path)?;
+				self.decoded = false;
+				continue;
+			};
+
+			let length = samples.len() / format.channels.max(1) as usize;
+
+			self.format = format;
+			self.pending = samples.chunks(length.max(1)).map(<[f32]>::to_vec).collect();
+			self.position = 0;
+			self.decoded = true;
+		}
+
+		let end = (self.position + frames).min(self.pending[0].len());
+		let block = self.pending.iter()
+			.map(|channel| channel[self.position..end].to_vec())
+			.collect();
+
+		self.position = end;
+
+		Ok(Some((self.format, block)))
+	}
+}
+
+impl AudioReader for FileReader {
+	fn read(&mut self, frames: usize) -> io::Result<Option<(AudioFormat, Vec<Vec<f32>>)>> {
+		self.read_frames(frames)
+			.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+	}
+}
+
+/// The first audio track of a file and a decoder for it.
+struct FileDecoder {
+	reader: Box<dyn FormatReader>,
+	decoder: Box<dyn Decoder>,
+	track_id: u32,
+}
+
+impl FileDecoder {
+	fn open(path: &Path) -> Result<Self, FileError> {
+		let file = File::open(path).map_err(SymphoniaError::from)?;
+		let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+		let mut hint = Hint::new();
+
+		if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+			hint.with_extension(extension);
+		}
+
+		let probed = symphonia::default::get_probe().format(
+			&hint,
+			stream,
+			&FormatOptions::default(),
+			&MetadataOptions::default(),
+		)?;
+
+		let reader = probed.format;
+
+		let track = reader.tracks().iter()
+			.find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+			.ok_or(FileError::NoAudioTrack)?;
+
+		let track_id = track.id;
+		let decoder = symphonia::default::get_codecs()
+			.make(&track.codec_params, &DecoderOptions::default())?;
+
+		Ok(Self { reader, decoder, track_id })
+	}
+
+	fn seek(&mut self, position: Duration) -> Result<(), FileError> {
+		if position.is_zero() {
+			return Ok(());
+		}
+
+		let time = Time::new(position.as_secs(), position.subsec_nanos() as f64 / 1e9);
+
+		self.reader.seek(SeekMode::Coarse, SeekTo::Time { time, track_id: Some(self.track_id) })?;
+		self.decoder.reset();
+
+		Ok(())
+	}
+
+	/// Decodes the next packet of the track into planar samples, one channel
+	/// after another. Returns `None` at the end of the file.
+	fn next_block(&mut self) -> Result<Option<(AudioFormat, Vec<f32>)>, FileError> {
+		loop {
+			let packet = match self.reader.next_packet() {
+				Ok(packet) => packet,
+				Err(SymphoniaError::IoError(error)) if error.kind() == io::ErrorKind::UnexpectedEof => {
+					return Ok(None);
+				},
+				Err(error) => return Err(error.into()),
+			};
+
+			if packet.track_id() != self.track_id {
+				continue;
+			}
+
+			let decoded = match self.decoder.decode(&packet) {
+				Ok(decoded) => decoded,
+				// a corrupt packet only loses its own samples
+				Err(SymphoniaError::DecodeError(_)) => continue,
+				Err(error) => return Err(error.into()),
+			};
+
+			let spec = *decoded.spec();
+			let format = AudioFormat {
+				rate: spec.rate,
+				channels: spec.channels.count() as u32,
+			};
+
+			let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+			samples.copy_planar_ref(decoded);
+
+			return Ok(Some((format, samples.samples().to_vec())));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RATE: u32 = 8000;
+
+	/// Writes `samples` as a 16 bit mono WAV file named after the test, which
+	/// is removed when dropped.
+	struct Wav(PathBuf);
+
+	impl Wav {
+		fn new(name: &str, samples: &[i16]) -> Self {
+			let data = samples.iter()
+				.flat_map(|sample| sample.to_le_bytes())
+				.collect::<Vec<_>>();
+
+			let mut bytes = Vec::new();
+			bytes.extend_from_slice(b"RIFF");
+			bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(b"WAVEfmt ");
+			bytes.extend_from_slice(&16u32.to_le_bytes());
+			// PCM, one channel
+			bytes.extend_from_slice(&1u16.to_le_bytes());
+			bytes.extend_from_slice(&1u16.to_le_bytes());
+			bytes.extend_from_slice(&RATE.to_le_bytes());
+			// bytes per second and per frame, then bits per sample
+			bytes.extend_from_slice(&(RATE * 2).to_le_bytes());
+			bytes.extend_from_slice(&2u16.to_le_bytes());
+			bytes.extend_from_slice(&16u16.to_le_bytes());
+			bytes.extend_from_slice(b"data");
+			bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(&data);
+
+			let path = std::env::temp_dir()
+				.join(format!("{}-{}-{}.wav", env!("CARGO_PKG_NAME"), std::process::id(), name));
+
+			std::fs::write(&path, bytes).unwrap();
+
+			Self(path)
+		}
+
+		fn reader(&self, looping: bool) -> FileReader {
+			FileReader::open(&self.0, FileOptions { looping, ..FileOptions::default() }).unwrap()
+		}
+	}
+
+	impl Drop for Wav {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+
+	fn ramp(length: usize) -> Vec<i16> {
+		(0..length).map(|i| i as i16 * 16).collect()
+	}
+
+	#[test]
+	fn blocks_are_no_larger_than_asked_until_the_end() {
+		let wav = Wav::new("blocks", &ramp(1000));
+		let mut reader = wav.reader(false);
+		let mut frames = 0;
+
+		while let Some((format, channels)) = reader.read_frames(300).unwrap() {
+			assert_eq!(format, AudioFormat { rate: RATE, channels: 1 });
+			assert_eq!(channels.len(), 1);
+			assert!(channels[0].len() <= 300, "{} frames read", channels[0].len());
+
+			frames += channels[0].len();
+		}
+
+		assert_eq!(frames, 1000);
+		assert!(reader.read_frames(300).unwrap().is_none());
+	}
+
+	#[test]
+	fn looping_starts_again_from_the_beginning() {
+		let wav = Wav::new("looping", &ramp(100));
+		let mut reader = wav.reader(true);
+		let mut samples = Vec::new();
+
+		while samples.len() < 250 {
+			let (_, channels) = reader.read_frames(64).unwrap().expect("a looping file never ends");
+			samples.extend_from_slice(&channels[0]);
+		}
+
+		assert_eq!(samples[..100], samples[100..200]);
+		assert_eq!(samples[..50], samples[200..250]);
+	}
+
+	#[test]
+	fn empty_file_ends_even_when_looping() {
+		let wav = Wav::new("empty", &[]);
+
+		assert!(wav.reader(true).read_frames(64).unwrap().is_none());
+		assert!(wav.reader(false).read_frames(64).unwrap().is_none());
+	}
+}
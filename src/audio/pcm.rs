@@ -0,0 +1,357 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use super::{AudioFormat, AudioReader, AudioSink, AudioSource, SourceState};
+use super::sample_format::{Encoding, SampleFormat};
+
+/// Most frames delivered at once.
+const BLOCK_FRAMES: usize = 256;
+
+/// How far reading can fall behind the wall clock before it stops trying to
+/// catch up, so a stalled writer isn't followed by a burst of fast audio.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Sample formats accepted as raw PCM, all interleaved and little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PcmFormat {
+	/// Signed 16 bit, MPD's default
+	S16le,
+	/// Signed 32 bit
+	S32le,
+	/// 32 bit float
+	F32le,
+}
+
+impl PcmFormat {
+	fn sample_format(self) -> SampleFormat {
+		match self {
+			PcmFormat::S16le => SampleFormat::interleaved(Encoding::S16, false),
+			PcmFormat::S32le => SampleFormat::interleaved(Encoding::S32, false),
+			PcmFormat::F32le => SampleFormat::interleaved(Encoding::F32, false),
+		}
+	}
+}
+
+/// Reads raw interleaved PCM from stdin or a file, such as the named pipe
+/// MPD's fifo output writes to. The format can't be detected so has to be
+/// given.
+pub(crate) struct PcmSource {
+	/// `-` for stdin
+	path: PathBuf,
+	format: AudioFormat,
+	sample_format: PcmFormat,
+	/// set while running, reads can't be interrupted so this is checked
+	/// before every delivery instead
+	stop: Option<Arc<AtomicBool>>,
+}
+
+impl PcmSource {
+	pub fn new(path: PathBuf, format: AudioFormat, sample_format: PcmFormat) -> Self {
+		Self {
+			path,
+			format,
+			sample_format,
+			stop: None,
+		}
+	}
+}
+
+impl AudioSource for PcmSource {
+	fn start(&mut self, sink: AudioSink) -> io::Result<()> {
+		if self.stop.is_some() {
+			return Ok(());
+		}
+
+		let stop = Arc::new(AtomicBool::new(false));
+
+		std::thread::Builder::new()
+			.name("pcm".to_owned())
+			.spawn({
+				let path = self.path.clone();
+				let format = self.format;
+				let sample_format = self.sample_format.sample_format();
+				let stop = Arc::clone(&stop);
+				move || {
+					if let Err(error) = read(&path, format, sample_format, &sink, &stop) {
+						eprintln!("Failed to read PCM from {}: {}", path.display(), error);
+					}
+
+					sink.set_state(SourceState::Idle);
+				}
+			})?;
+
+		self.stop = Some(stop);
+
+		Ok(())
+	}
+
+	/// The thread may still be blocked reading, but won't deliver anything
+	/// else and exits once the read returns.
+	fn stop(&mut self) {
+		if let Some(stop) = self.stop.take() {
+			stop.store(true, Ordering::Relaxed);
+		}
+	}
+
+	fn format(&self) -> Option<AudioFormat> {
+		Some(self.format)
+	}
+}
+
+/// Reads raw PCM as quickly as it is asked for, rather than in real time.
+/// Unlike [`PcmSource`] a named pipe is only read until its writer closes it.
+pub(crate) struct PcmReader {
+	reader: Box<dyn Read>,
+	format: AudioFormat,
+	sample_format: SampleFormat,
+}
+
+impl PcmReader {
+	pub fn open(path: &Path, format: AudioFormat, sample_format: PcmFormat) -> io::Result<Self> {
+		let reader: Box<dyn Read> = if path == Path::new("-") {
+			Box::new(io::stdin())
+		} else {
+			Box::new(File::open(path)?)
+		};
+
+		Ok(Self {
+			reader,
+			format,
+			sample_format: sample_format.sample_format(),
+		})
+	}
+}
+
+impl AudioReader for PcmReader {
+	fn read(&mut self, frames: usize) -> io::Result<Option<(AudioFormat, Vec<Vec<f32>>)>> {
+		let channel_count = self.format.channels as usize;
+		let frame_size = self.sample_format.sample_size() * channel_count;
+		let mut bytes = vec![0; frame_size * frames];
+		let mut filled = 0;
+
+		while filled < bytes.len() {
+			match self.reader.read(&mut bytes[filled..]) {
+				Ok(0) => break,
+				Ok(read) => filled += read,
+				Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+				Err(error) => return Err(error),
+			}
+		}
+
+		if filled < frame_size {
+			return Ok(None);
+		}
+
+		Ok(Some((self.format, self.sample_format.decode_interleaved(&bytes[..filled], channel_count))))
+	}
+}
+
+/// Delivers PCM from `path` until stdin ends or `stop` is set. A named pipe is
+/// opened again whenever its writer closes it, as players do between songs.
+fn read(
+	path: &Path,
+	format: AudioFormat,
+	sample_format: SampleFormat,
+	sink: &AudioSink,
+	stop: &AtomicBool,
+) -> io::Result<()> {
+	let mut clock = Clock::new(format.rate);
+	let mut deliver = |channels: &[&[f32]]| sink.deliver(channels, format.rate);
+
+	if path == Path::new("-") {
+		return read_stream(io::stdin().lock(), format, sample_format, &mut deliver, stop, &mut clock);
+	}
+
+	while !stop.load(Ordering::Relaxed) {
+		// blocks until there is a writer
+		let file = File::open(path)?;
+		let is_file = file.metadata()?.is_file();
+
+		read_stream(file, format, sample_format, &mut deliver, stop, &mut clock)?;
+
+		if is_file {
+			break;
+		}
+
+		// nothing arrives until the next writer opens the pipe
+		sink.set_state(SourceState::Idle);
+	}
+
+	Ok(())
+}
+
+/// Passes each block of whole frames read to `deliver` until the reader ends.
+fn read_stream(
+	mut reader: impl Read,
+	format: AudioFormat,
+	sample_format: SampleFormat,
+	deliver: &mut impl FnMut(&[&[f32]]),
+	stop: &AtomicBool,
+	clock: &mut Clock,
+) -> io::Result<()> {
+	let channel_count = format.channels as usize;
+	let frame_size = sample_format.sample_size() * channel_count;
+	let mut bytes = vec![0; frame_size * BLOCK_FRAMES];
+	// bytes of a partial frame left over from the previous read
+	let mut filled = 0;
+
+	loop {
+		let read = match reader.read(&mut bytes[filled..]) {
+			Ok(0) => return Ok(()),
+			Ok(read) => read,
+			Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		};
+
+		if stop.load(Ordering::Relaxed) {
+			return Ok(());
+		}
+
+		filled += read;
+
+		let whole = filled - filled % frame_size;
+
+		if whole == 0 {
+			continue;
+		}
+
+		let channels = sample_format.decode_interleaved(&bytes[..whole], channel_count);
+		let channels = channels.iter()
+			.map(Vec::as_slice)
+			.collect::<Vec<_>>();
+
+		clock.wait(whole / frame_size);
+		deliver(&channels);
+
+		bytes.copy_within(whole..filled, 0);
+		filled -= whole;
+	}
+}
+
+/// Paces reads from something faster than real time, like a file piped to
+/// stdin, while passing on a writer which is already paced without delay.
+struct Clock {
+	rate: u32,
+	started: Instant,
+	frames: u64,
+}
+
+impl Clock {
+	fn new(rate: u32) -> Self {
+		Self {
+			rate,
+			started: Instant::now(),
+			frames: 0,
+		}
+	}
+
+	/// Waits until the audio before the next `frames` should have played.
+	fn wait(&mut self, frames: usize) {
+		let due = self.started + Duration::from_secs_f64(self.frames as f64 / self.rate as f64);
+		let now = Instant::now();
+
+		match due.checked_duration_since(now) {
+			Some(wait) => std::thread::sleep(wait),
+			None if now - due > MAX_LAG => {
+				self.started = now;
+				self.frames = 0;
+			},
+			None => {},
+		}
+
+		self.frames += frames as u64;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	const STEREO: AudioFormat = AudioFormat { rate: 48000, channels: 2 };
+
+	/// Gives at most three bytes per read, so frames of 16 bit stereo are
+	/// always split between reads.
+	struct Trickle(Cursor<Vec<u8>>);
+
+	impl Read for Trickle {
+		fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+			let length = buffer.len().min(3);
+			self.0.read(&mut buffer[..length])
+		}
+	}
+
+	fn s16(values: &[i16]) -> Vec<u8> {
+		values.iter().flat_map(|value| value.to_le_bytes()).collect()
+	}
+
+	#[test]
+	fn partial_frames_are_carried_to_the_next_read() {
+		let bytes = s16(&[0x4000, -0x8000, 0, 0x4000, -0x4000, 0]);
+		let mut channels = [Vec::new(), Vec::new()];
+
+		read_stream(
+			Trickle(Cursor::new(bytes)),
+			STEREO,
+			PcmFormat::S16le.sample_format(),
+			&mut |block: &[&[f32]]| {
+				for (channel, values) in channels.iter_mut().zip(block) {
+					channel.extend_from_slice(values);
+				}
+			},
+			&AtomicBool::new(false),
+			&mut Clock::new(STEREO.rate),
+		).unwrap();
+
+		assert_eq!(channels, [vec![0.5, 0.0, -0.5], vec![-1.0, 0.5, 0.0]]);
+	}
+
+	#[test]
+	fn reader_ends_at_a_partial_frame() {
+		let mut bytes = s16(&[0x4000, -0x4000]);
+		bytes.extend_from_slice(&[0x00, 0x40, 0x00]);
+
+		let mut reader = PcmReader {
+			reader: Box::new(Cursor::new(bytes)),
+			format: STEREO,
+			sample_format: PcmFormat::S16le.sample_format(),
+		};
+
+		let (format, channels) = reader.read(1).unwrap().unwrap();
+		assert_eq!(format, STEREO);
+		assert_eq!(channels, [vec![0.5], vec![-0.5]]);
+
+		assert!(reader.read(1).unwrap().is_none());
+	}
+
+	#[test]
+	fn clock_starts_again_after_falling_too_far_behind() {
+		let mut clock = Clock::new(STEREO.rate);
+		clock.started -= MAX_LAG * 2;
+
+		let before = Instant::now();
+		clock.wait(100);
+
+		assert!(clock.started >= before);
+		assert_eq!(clock.frames, 100);
+	}
+
+	#[test]
+	fn clock_keeps_small_lags() {
+		let mut clock = Clock::new(STEREO.rate);
+		let started = clock.started - MAX_LAG / 2;
+		clock.started = started;
+
+		clock.wait(100);
+
+		assert_eq!(clock.started, started);
+		assert_eq!(clock.frames, 100);
+	}
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use pipewire::{stream::*, properties, spa::{Direction, data::Data, pod::{deserialize::PodDeserializer, Object, Value}, utils::Id}, MainLoop};
+use pipewire::channel::{self, Receiver, Sender};
+
+use self::capture::Command;
+use self::pod_choice_default::Fixate;
+
+use super::{AudioFormat, AudioSink, AudioSource, SourceState};
+use super::sample_format::{self, SampleFormat};
+
+pub(crate) use self::nodes::Node;
+pub(crate) use self::playback::Playback;
+
+mod spa_audio_info_raw;
+mod pod_choice_default;
+mod nodes;
+mod playback;
+mod capture;
+mod session;
+
+#[derive(Debug, Clone, Copy)]
+struct StreamConfiguration {
+	format: AudioFormat,
+	sample_format: SampleFormat,
+}
+
+/// Why a negotiated format can't be captured.
+#[derive(Debug)]
+enum FormatError {
+	/// the parameter isn't a format object
+	NotAnObject,
+	Missing(&'static str),
+	Invalid(&'static str),
+	NotRawAudio,
+	UnsupportedSampleFormat(u32),
+}
+
+impl fmt::Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FormatError::NotAnObject => write!(f, "it couldn't be read"),
+			FormatError::Missing(property) => write!(f, "it has no {}", property),
+			FormatError::Invalid(property) => write!(f, "its {} is invalid", property),
+			FormatError::NotRawAudio => write!(f, "it isn't raw audio"),
+			FormatError::UnsupportedSampleFormat(format) => {
+				write!(f, "sample format {} isn't supported", format)
+			},
+		}
+	}
+}
+
+impl StreamConfiguration {
+	fn from_pod(pod: NonNull<libspa_sys::spa_pod>) -> Result<Self, FormatError> {
+		let object = match unsafe { PodDeserializer::deserialize_ptr::<Value>(pod) } {
+			Ok(Value::Object(object)) => object,
+			_ => return Err(FormatError::NotAnObject),
+		};
+
+		let media_type: Id = property(&object, libspa_sys::SPA_FORMAT_mediaType, "media type")?;
+		let media_subtype: Id = property(&object, libspa_sys::SPA_FORMAT_mediaSubtype, "media subtype")?;
+
+		let is_audio = media_type.0 == libspa_sys::SPA_MEDIA_TYPE_audio;
+		let is_raw = media_subtype.0 == libspa_sys::SPA_MEDIA_SUBTYPE_raw;
+
+		if !(is_audio && is_raw) {
+			return Err(FormatError::NotRawAudio);
+		}
+
+		let format: Id = property(&object, libspa_sys::SPA_FORMAT_AUDIO_format, "sample format")?;
+		let rate: i32 = property(&object, libspa_sys::SPA_FORMAT_AUDIO_rate, "rate")?;
+		let channels: i32 = property(&object, libspa_sys::SPA_FORMAT_AUDIO_channels, "channel count")?;
+
+		let sample_format = SampleFormat::from_spa(format.0)
+			.ok_or(FormatError::UnsupportedSampleFormat(format.0))?;
+
+		let format = AudioFormat {
+			rate: u32::try_from(rate).ok().filter(|&rate| rate > 0)
+				.ok_or(FormatError::Invalid("rate"))?,
+			channels: u32::try_from(channels).ok().filter(|&channels| channels > 0)
+				.ok_or(FormatError::Invalid("channel count"))?,
+		};
+
+		Ok(Self { format, sample_format })
+	}
+}
+
+/// The fixed value of a property of a format.
+fn property<T>(object: &Object, key: u32, name: &'static str) -> Result<T, FormatError>
+where
+	Value: Fixate<T>,
+{
+	object.properties.iter()
+		.find(|property| property.key == key)
+		.ok_or(FormatError::Missing(name))?
+		.value
+		.fixate()
+		.map_err(|()| FormatError::Invalid(name))
+}
+
+struct StreamData {
+	configuration: Option<StreamConfiguration>,
+	sink: AudioSink,
+	/// shared with the source so it can report the negotiated format
+	format: Arc<Mutex<Option<AudioFormat>>>,
+}
+
+/// Finds the audio node which matches `target` by name, description or serial.
+pub(crate) fn find_node(target: &str) -> Result<Option<Node>, pipewire::Error> {
+	let mainloop = MainLoop::new()?;
+
+	let node = nodes::list(&mainloop)?
+		.into_iter()
+		.find(|node| node.matches(target));
+
+	Ok(node)
+}
+
+pub(crate) fn list_nodes() -> Result<(), pipewire::Error> {
+	let mainloop = MainLoop::new()?;
+	let nodes = nodes::list(&mainloop)?;
+
+	println!("{:>8}  {:<20}  name (description)", "serial", "class");
+
+	for node in nodes {
+		let serial = node.serial.as_deref().unwrap_or("-");
+
+		match &node.description {
+			Some(description) => {
+				println!("{:>8}  {:<20}  {} ({})", serial, node.media_class, node.name, description)
+			},
+			None => println!("{:>8}  {:<20}  {}", serial, node.media_class, node.name),
+		}
+	}
+
+	Ok(())
+}
+
+/// Captures audio from a PipeWire node, or the default sink if there is none.
+/// Reconnects whenever the connection is lost, the target comes back or the
+/// default sink changes.
+pub(crate) struct PipeWireSource {
+	target: Option<Node>,
+	commands: Sender<Command>,
+	/// taken by the capture thread while it runs
+	receiver: Option<Receiver<Command>>,
+	format: Arc<Mutex<Option<AudioFormat>>>,
+	running: Option<Running>,
+}
+
+struct Running {
+	stop: Sender<()>,
+	/// returns the command receiver, and the target it was last connected to
+	thread: JoinHandle<(Receiver<Command>, Option<Node>)>,
+}
+
+/// Moves a running [`PipeWireSource`] to a different node.
+pub(crate) struct TargetSender(Sender<Command>);
+
+impl TargetSender {
+	/// Looks up the node matching `target`, or goes back to the default sink
+	/// if there is none. Keeps the current node if nothing matches or the
+	/// nodes can't be listed.
+	pub fn send(&self, target: Option<&str>) {
+		let node = match target.map(find_node).transpose() {
+			Ok(Some(None)) => {
+				return eprintln!("No audio node matches {:?}, keeping the current one", target);
+			},
+			Ok(node) => node.flatten(),
+			Err(error) => {
+				return eprintln!("Failed to look up {:?}, keeping the current node: {}", target, error);
+			},
+		};
+
+		let _ = self.0.send(Command::Target(node));
+	}
+}
+
+impl PipeWireSource {
+	pub fn new(target: Option<Node>) -> (Self, TargetSender) {
+		let (commands, receiver) = channel::channel();
+		let target_sender = TargetSender(commands.clone());
+
+		let source = Self {
+			target,
+			commands,
+			receiver: Some(receiver),
+			format: Arc::new(Mutex::new(None)),
+			running: None,
+		};
+
+		(source, target_sender)
+	}
+}
+
+impl AudioSource for PipeWireSource {
+	fn start(&mut self, sink: AudioSink) -> std::io::Result<()> {
+		if self.running.is_some() {
+			return Ok(());
+		}
+
+		// only missing if a previous thread failed to spawn, in which case the
+		// target can no longer be changed
+		let receiver = self.receiver.take().unwrap_or_else(|| {
+			let (commands, receiver) = channel::channel();
+			self.commands = commands;
+			receiver
+		});
+
+		let (stop, stop_receiver) = channel::channel();
+		let commands = (self.commands.clone(), receiver);
+		let target = self.target.clone();
+		let format = Arc::clone(&self.format);
+
+		let thread = std::thread::Builder::new()
+			.name("pipewire".to_owned())
+			.spawn(move || capture::run(sink, format, commands, stop_receiver, target));
+
+		self.running = Some(Running { stop, thread: thread? });
+
+		Ok(())
+	}
+
+	fn stop(&mut self) {
+		let Some(Running { stop, thread }) = self.running.take() else {
+			return;
+		};
+
+		let _ = stop.send(());
+
+		match thread.join() {
+			Ok((receiver, target)) => {
+				self.receiver = Some(receiver);
+				self.target = target;
+			},
+			Err(_) => eprintln!("Audio capture thread panicked"),
+		}
+
+		*self.format.lock().unwrap() = None;
+	}
+
+	fn format(&self) -> Option<AudioFormat> {
+		*self.format.lock().unwrap()
+	}
+}
+
+fn stream(
+	mainloop: &MainLoop,
+	sink: AudioSink,
+	format: Arc<Mutex<Option<AudioFormat>>>,
+	target: Option<&Node>,
+	commands: Sender<Command>,
+	generation: u64,
+) -> Result<Stream<StreamData>, pipewire::Error> {
+	*format.lock().unwrap() = None;
+
+	let capture_sink = target.map(Node::is_sink).unwrap_or(true);
+
+	let mut properties = properties! {
+		*pipewire::keys::NODE_NAME => env!("CARGO_PKG_NAME"),
+		*pipewire::keys::MEDIA_TYPE => "Audio",
+		*pipewire::keys::MEDIA_CATEGORY => "Capture",
+		*pipewire::keys::STREAM_CAPTURE_SINK => if capture_sink { "true" } else { "false" },
+	};
+
+	if let Some(target) = target {
+		properties.insert("target.object", target.target_object());
+	}
+
+	let stream = Stream::<StreamData>::with_user_data(
+		mainloop,
+		"audio-capture",
+		properties,
+		StreamData {
+			configuration: None,
+			sink: sink.clone(),
+			format,
+		},
+	)
+	.state_changed(move |_, state| match state {
+		StreamState::Error(message) => {
+			eprintln!("Audio stream failed: {}", message);
+			let _ = commands.send(Command::Failed(generation));
+		},
+		// the node it was linked to went away
+		StreamState::Unconnected => { let _ = commands.send(Command::Failed(generation)); },
+		StreamState::Connecting => {},
+		StreamState::Paused => sink.set_state(SourceState::Idle),
+		StreamState::Streaming => { let _ = commands.send(Command::Streaming(generation)); },
+	})
+	.param_changed(|id, data, raw_pod| {
+		if id != libspa_sys::SPA_PARAM_Format {
+			return;
+		}
+
+		// there is no format while the stream is being renegotiated, so
+		// buffers are skipped until the next one arrives
+		let configuration = NonNull::new(raw_pod.cast_mut()).map(|pod| {
+			StreamConfiguration::from_pod(pod).map_err(|error| {
+				eprintln!("Can't capture the negotiated audio format, {}", error);
+			})
+		});
+
+		data.configuration = configuration.and_then(Result::ok);
+		*data.format.lock().unwrap() = data.configuration.map(|configuration| configuration.format);
+	})
+	.process(|stream, StreamData { configuration, sink, .. }| {
+		// dropping a buffer gives it back to the stream
+		let Some(mut buffer) = stream.dequeue_buffer() else {
+			return;
+		};
+
+		let Some(configuration) = *configuration else {
+			return;
+		};
+
+		let AudioFormat { rate, channels: channel_count } = configuration.format;
+		let format = configuration.sample_format;
+		let datas = buffer.datas_mut();
+
+		if format.planar {
+			let mut channels = datas.iter_mut()
+				.take(channel_count as usize)
+				.map(|data| chunk_bytes(data).map_or_else(Vec::new, |bytes| format.decode(bytes)))
+				.collect::<Vec<_>>();
+
+			// a missing block is silence, so every channel keeps its place
+			let frames = channels.iter().map(Vec::len).max().unwrap_or(0);
+			channels.resize_with(channel_count as usize, Vec::new);
+
+			for channel in &mut channels {
+				channel.resize(frames, 0.0);
+			}
+
+			let channels = channels.iter()
+				.map(Vec::as_slice)
+				.collect::<Vec<_>>();
+
+			sink.deliver(&channels, rate)
+		} else if let Some(bytes) = datas.get_mut(0).and_then(chunk_bytes) {
+			let channels = format.decode_interleaved(bytes, channel_count as usize);
+
+			let channels = channels.iter()
+				.map(Vec::as_slice)
+				.collect::<Vec<_>>();
+
+			sink.deliver(&channels, rate)
+		}
+	})
+	.create()?;
+
+	let params = spa_audio_info_raw::SpaAudioInfoRaw {
+		formats: sample_format::PREFERRED_FORMATS.to_vec(),
+		..spa_audio_info_raw::SpaAudioInfoRaw::empty()
+	}.as_pod().unwrap();
+
+	stream.connect(
+		Direction::Input,
+		None,
+		StreamFlags::AUTOCONNECT | StreamFlags::RT_PROCESS | StreamFlags::MAP_BUFFERS,
+		&mut [params.as_ptr().cast()],
+	)?;
+
+	Ok(stream)
+}
+
+/// The valid part of a data block.
+fn chunk_bytes(data: &mut Data) -> Option<&[u8]> {
+	let chunk = data.chunk();
+	let offset = chunk.offset() as usize;
+	let size = chunk.size() as usize;
+
+	data.data()
+		.and_then(|bytes| bytes.get(offset..offset + size))
+}
\ No newline at end of file
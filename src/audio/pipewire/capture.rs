@@ -0,0 +1,211 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pipewire::MainLoop;
+use pipewire::channel::{Receiver, Sender};
+use pipewire::stream::Stream;
+
+use crate::audio::{AudioFormat, AudioSink, SourceState};
+
+use super::session::Session;
+use super::{stream, Node, StreamData};
+
+/// Delay before the first attempt to reconnect, doubled after every failure
+/// in a row.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Longest delay between attempts to reconnect.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Messages handled on the capture thread. Those about a connection carry its
+/// generation, so any about one which has since been replaced are ignored.
+pub(super) enum Command {
+	/// Connect to a different node, or follow the default sink
+	Target(Option<Node>),
+	/// Connect again straight away, as the node to capture has changed
+	Refresh(u64),
+	/// The connection failed or was lost
+	Failed(u64),
+	/// The stream is running, so earlier failures are forgotten
+	Streaming(u64),
+}
+
+/// The capture stream and the session watching for changes to what it is
+/// connected to, replaced together whenever it reconnects.
+struct Connection {
+	// the stream goes first so it stops delivering before anything else
+	_stream: Stream<StreamData>,
+	_session: Session,
+}
+
+/// Everything on the capture thread.
+struct Capture {
+	mainloop: MainLoop,
+	sink: AudioSink,
+	format: Arc<Mutex<Option<AudioFormat>>>,
+	commands: Sender<Command>,
+	/// shared with the session, which refreshes it when the node comes back
+	target: Rc<RefCell<Option<Node>>>,
+	generation: u64,
+	/// failed attempts to connect since the stream last ran
+	failures: u32,
+	/// when to connect again after a failure, the main loop is quit whenever
+	/// it changes so `run` can set the timer to match
+	retry_at: Option<Instant>,
+	connection: Option<Connection>,
+}
+
+/// Captures audio until `stop` receives, reconnecting whenever the connection
+/// is lost. Returns the command receiver and target to start again with.
+pub(super) fn run(
+	sink: AudioSink,
+	format: Arc<Mutex<Option<AudioFormat>>>,
+	commands: (Sender<Command>, Receiver<Command>),
+	stop: Receiver<()>,
+	target: Option<Node>,
+) -> (Receiver<Command>, Option<Node>) {
+	let mainloop = MainLoop::new().unwrap();
+	let (sender, receiver) = commands;
+
+	let capture = Rc::new(RefCell::new(Capture {
+		mainloop: mainloop.clone(),
+		sink,
+		format,
+		commands: sender,
+		target: Rc::new(RefCell::new(target)),
+		generation: 0,
+		failures: 0,
+		retry_at: None,
+		connection: None,
+	}));
+
+	capture.borrow_mut().connect();
+
+	let timer = mainloop.add_timer({
+		let capture = Rc::clone(&capture);
+		move |_| capture.borrow_mut().retry_if_due()
+	});
+
+	let receiver = receiver.attach(&mainloop, {
+		let capture = Rc::clone(&capture);
+		move |command| capture.borrow_mut().handle(command)
+	});
+
+	let stopped = Rc::new(Cell::new(false));
+
+	let _stop = stop.attach(&mainloop, {
+		let stopped = Rc::clone(&stopped);
+		let mainloop = mainloop.clone();
+
+		move |()| {
+			stopped.set(true);
+			mainloop.quit();
+		}
+	});
+
+	// a timer source borrows the main loop so can't be kept by the capture,
+	// instead the capture quits the loop when the retry changes and the
+	// timer is set again here, one-shot and only while a retry is waiting
+	while !stopped.get() {
+		let delay = capture.borrow().retry_at.map(|retry_at| {
+			// a zero delay would disarm the timer
+			retry_at.saturating_duration_since(Instant::now()).max(Duration::from_nanos(1))
+		});
+
+		if let Err(error) = timer.update_timer(delay, None).into_sync_result() {
+			eprintln!("Failed to set the reconnect timer, the connection won't be retried: {}", error);
+		}
+
+		mainloop.run();
+	}
+
+	let target = {
+		let mut capture = capture.borrow_mut();
+
+		// the stream has to go before the main loop it belongs to
+		capture.connection = None;
+		capture.target.borrow_mut().take()
+	};
+
+	(receiver.deattach(), target)
+}
+
+impl Capture {
+	fn handle(&mut self, command: Command) {
+		match command {
+			Command::Target(target) => {
+				*self.target.borrow_mut() = target;
+				self.failures = 0;
+				self.connect();
+			},
+			Command::Refresh(generation) if generation == self.generation => self.connect(),
+			Command::Failed(generation) if generation == self.generation => self.retry_later(),
+			Command::Streaming(generation) if generation == self.generation => self.failures = 0,
+			// about a connection which has since been replaced
+			_ => {},
+		}
+	}
+
+	/// Replaces the connection with a new one to the current target.
+	fn connect(&mut self) {
+		// disconnect first so two streams never fill the buffer at once
+		self.connection = None;
+		self.generation += 1;
+		self.sink.set_state(SourceState::Connecting);
+
+		// disarms the timer
+		if self.retry_at.take().is_some() {
+			self.mainloop.quit();
+		}
+
+		match self.new_connection() {
+			Ok(connection) => self.connection = Some(connection),
+			Err(error) => {
+				eprintln!("Failed to connect to PipeWire: {}", error);
+				self.retry_later();
+			},
+		}
+	}
+
+	fn new_connection(&self) -> Result<Connection, pipewire::Error> {
+		let target = self.target.borrow().clone();
+
+		let session = Session::new(
+			&self.mainloop,
+			self.commands.clone(),
+			self.generation,
+			Rc::clone(&self.target),
+		)?;
+
+		let stream = stream(
+			&self.mainloop,
+			self.sink.clone(),
+			Arc::clone(&self.format),
+			target.as_ref(),
+			self.commands.clone(),
+			self.generation,
+		)?;
+
+		Ok(Connection { _stream: stream, _session: session })
+	}
+
+	/// Drops the connection and tries again after a delay, which grows with
+	/// every failure in a row so a missing daemon isn't polled constantly.
+	fn retry_later(&mut self) {
+		self.connection = None;
+		self.generation += 1;
+		self.sink.set_state(SourceState::Disconnected);
+
+		let delay = RETRY_DELAY.saturating_mul(1 << self.failures.min(16)).min(MAX_RETRY_DELAY);
+		self.failures += 1;
+		self.retry_at = Some(Instant::now() + delay);
+		self.mainloop.quit();
+	}
+
+	fn retry_if_due(&mut self) {
+		if self.retry_at.map_or(false, |retry_at| Instant::now() >= retry_at) {
+			self.connect();
+		}
+	}
+}
@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pipewire::{Context, MainLoop, registry::GlobalObject, types::ObjectType, spa::{ForeignDict, ReadableDict}};
+
+/// An audio node which a capture stream can be connected to.
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+	pub serial: Option<String>,
+	pub name: String,
+	pub description: Option<String>,
+	pub media_class: String,
+}
+
+impl Node {
+	pub fn from_global(global: &GlobalObject<ForeignDict>) -> Option<Self> {
+		if global.type_ != ObjectType::Node {
+			return None;
+		}
+
+		let properties = global.props.as_ref()?;
+		let media_class = properties.get(*pipewire::keys::MEDIA_CLASS)?;
+
+		let is_audio = media_class.starts_with("Audio/")
+			|| media_class == "Stream/Output/Audio";
+
+		if !is_audio {
+			return None;
+		}
+
+		Some(Self {
+			serial: properties.get("object.serial").map(String::from),
+			name: properties.get(*pipewire::keys::NODE_NAME)?.to_owned(),
+			description: properties.get(*pipewire::keys::NODE_DESCRIPTION).map(String::from),
+			media_class: media_class.to_owned(),
+		})
+	}
+
+	/// Sinks are captured through their monitor ports rather than directly.
+	pub fn is_sink(&self) -> bool {
+		self.media_class == "Audio/Sink"
+	}
+
+	pub fn matches(&self, target: &str) -> bool {
+		self.name == target
+			|| self.serial.as_deref() == Some(target)
+			|| self.description.as_deref() == Some(target)
+	}
+
+	/// The value to use for `target.object` when connecting a stream.
+	pub fn target_object(&self) -> &str {
+		self.serial.as_deref().unwrap_or(&self.name)
+	}
+}
+
+/// Collects all the audio nodes currently known to the PipeWire daemon.
+pub fn list(mainloop: &MainLoop) -> Result<Vec<Node>, pipewire::Error> {
+	let context = Context::new(mainloop)?;
+	let core = context.connect(None)?;
+	let registry = core.get_registry()?;
+
+	let nodes = Rc::new(RefCell::new(Vec::new()));
+
+	let _registry_listener = registry.add_listener_local()
+		.global({
+			let nodes = Rc::clone(&nodes);
+			move |global| {
+				if let Some(node) = Node::from_global(global) {
+					nodes.borrow_mut().push(node);
+				}
+			}
+		})
+		.register();
+
+	// the registry announces every existing global before the sync completes
+	let pending = core.sync(0)?;
+
+	let _core_listener = core.add_listener_local()
+		.done({
+			let mainloop = mainloop.clone();
+			move |id, seq| {
+				if id == pipewire::PW_ID_CORE && seq == pending {
+					mainloop.quit();
+				}
+			}
+		})
+		.register();
+
+	mainloop.run();
+
+	let nodes = std::mem::take(&mut *nodes.borrow_mut());
+	Ok(nodes)
+}
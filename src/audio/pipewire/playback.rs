@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use pipewire::{stream::*, properties, spa::Direction, MainLoop};
+use pipewire::channel::{self, Sender};
+
+use crate::audio::AudioFormat;
+
+use super::spa_audio_info_raw::SpaAudioInfoRaw;
+
+/// Most audio kept waiting to be played, in seconds. The file is paced by the
+/// wall clock and the output by its own, so any more is dropped rather than
+/// letting the two drift apart.
+const MAX_QUEUED: f32 = 0.25;
+
+/// Most frames written each time the output asks for more, which is also the
+/// latency asked for. The mapped buffers are usually much larger, and filling
+/// them would play far ahead of the picture.
+const QUANTUM_FRAMES: usize = 1024;
+
+/// Plays samples through the default output as they are queued.
+pub(crate) struct Playback {
+	format: AudioFormat,
+	/// interleaved samples waiting to be played
+	queue: Arc<Mutex<VecDeque<f32>>>,
+	stop: Sender<()>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Playback {
+	pub fn start(format: AudioFormat) -> io::Result<Self> {
+		let queue = Arc::new(Mutex::new(VecDeque::new()));
+		let (stop, stop_receiver) = channel::channel();
+
+		let thread = std::thread::Builder::new()
+			.name("playback".to_owned())
+			.spawn({
+				let queue = Arc::clone(&queue);
+				move || {
+					let mainloop = MainLoop::new().unwrap();
+
+					let stream = match stream(&mainloop, format, queue) {
+						Ok(stream) => stream,
+						Err(error) => {
+							eprintln!("Failed to create playback stream: {}", error);
+							return;
+						},
+					};
+
+					let _stop = stop_receiver.attach(&mainloop, {
+						let mainloop = mainloop.clone();
+						move |()| mainloop.quit()
+					});
+
+					mainloop.run();
+
+					// the stream has to go before the main loop it belongs to
+					drop(stream);
+				}
+			})?;
+
+		Ok(Self {
+			format,
+			queue,
+			stop,
+			thread: Some(thread),
+		})
+	}
+
+	pub fn format(&self) -> AudioFormat {
+		self.format
+	}
+
+	/// Queues a block of samples, with a slice for each channel.
+	pub fn queue(&self, channels: &[&[f32]]) {
+		let frames = channels.iter().map(|channel| channel.len()).min().unwrap_or(0);
+		let mut queue = self.queue.lock().unwrap();
+
+		for frame in 0..frames {
+			queue.extend(channels.iter().map(|channel| channel[frame]));
+		}
+
+		let limit = (MAX_QUEUED * self.format.rate as f32) as usize * channels.len();
+
+		if queue.len() > limit {
+			let excess = queue.len() - limit;
+			queue.drain(..excess);
+		}
+	}
+}
+
+impl Drop for Playback {
+	fn drop(&mut self) {
+		let _ = self.stop.send(());
+
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+fn stream(
+	mainloop: &MainLoop,
+	format: AudioFormat,
+	queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<Stream<Arc<Mutex<VecDeque<f32>>>>, pipewire::Error> {
+	let mut properties = properties! {
+		*pipewire::keys::NODE_NAME => env!("CARGO_PKG_NAME"),
+		*pipewire::keys::MEDIA_TYPE => "Audio",
+		*pipewire::keys::MEDIA_CATEGORY => "Playback",
+		*pipewire::keys::MEDIA_ROLE => "Music",
+	};
+
+	properties.insert("node.latency", format!("{}/{}", QUANTUM_FRAMES, format.rate));
+
+	let stride = 4 * format.channels as usize;
+
+	let stream = Stream::with_user_data(
+		mainloop,
+		"audio-playback",
+		properties,
+		queue,
+	)
+	.process(move |stream, queue| {
+		let Some(mut buffer) = stream.dequeue_buffer() else {
+			return;
+		};
+
+		let data = &mut buffer.datas_mut()[0];
+
+		let frames = match data.data() {
+			Some(bytes) => {
+				let frames = (bytes.len() / stride).min(QUANTUM_FRAMES);
+				let mut queue = queue.lock().unwrap();
+
+				// anything not yet decoded is played as silence
+				for sample in bytes[..frames * stride].chunks_exact_mut(4) {
+					let value = queue.pop_front().unwrap_or(0.0);
+					sample.copy_from_slice(&value.to_le_bytes());
+				}
+
+				frames
+			},
+			None => 0,
+		};
+
+		let chunk = data.chunk_mut();
+		*chunk.offset_mut() = 0;
+		*chunk.stride_mut() = stride as i32;
+		*chunk.size_mut() = (frames * stride) as u32;
+	})
+	.create()?;
+
+	let channels = match format.channels {
+		1 => vec![Some(libspa_sys::SPA_AUDIO_CHANNEL_MONO)],
+		2 => vec![Some(libspa_sys::SPA_AUDIO_CHANNEL_FL), Some(libspa_sys::SPA_AUDIO_CHANNEL_FR)],
+		count => vec![None; count as usize],
+	};
+
+	let flags = match format.channels {
+		1 | 2 => 0,
+		_ => libspa_sys::SPA_AUDIO_FLAG_UNPOSITIONED,
+	};
+
+	let params = SpaAudioInfoRaw {
+		formats: vec![libspa_sys::SPA_AUDIO_FORMAT_F32_LE],
+		flags,
+		rate: format.rate,
+		channels,
+	}.as_pod().unwrap();
+
+	stream.connect(
+		Direction::Output,
+		None,
+		StreamFlags::AUTOCONNECT | StreamFlags::RT_PROCESS | StreamFlags::MAP_BUFFERS,
+		&mut [params.as_ptr().cast()],
+	)?;
+
+	Ok(stream)
+}
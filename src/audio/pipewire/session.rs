@@ -0,0 +1,143 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use pipewire::{Context, Core, MainLoop, registry::{self, GlobalObject, Registry}, types::ObjectType, spa::{ForeignDict, ReadableDict}};
+use pipewire::channel::Sender;
+use pipewire::metadata::{Metadata, MetadataListener};
+
+use super::capture::Command;
+use super::Node;
+
+/// Key of the sink streams without a target are connected to, in the
+/// `default` metadata.
+const DEFAULT_SINK_KEY: &str = "default.audio.sink";
+
+/// Watches for changes which mean the capture stream has to connect again:
+/// the default sink changing while there is no target, or the target going
+/// away or coming back.
+///
+/// It has its own connection to the daemon, so is replaced along with the
+/// stream rather than outliving a restart.
+pub(super) struct Session {
+	// listeners go before what they listen to
+	_metadata: Rc<RefCell<Option<(MetadataListener, Metadata)>>>,
+	_registry_listener: registry::Listener,
+	_registry: Rc<Registry>,
+	_core: Core,
+	_context: Context<MainLoop>,
+}
+
+impl Session {
+	pub fn new(
+		mainloop: &MainLoop,
+		commands: Sender<Command>,
+		generation: u64,
+		target: Rc<RefCell<Option<Node>>>,
+	) -> Result<Self, pipewire::Error> {
+		let context = Context::new(mainloop)?;
+		let core = context.connect(None)?;
+		let registry = Rc::new(core.get_registry()?);
+
+		let metadata = Rc::new(RefCell::new(None));
+		// to notice the target going away, as only the id is given then
+		let target_id = Rc::new(Cell::new(None));
+
+		let registry_listener = registry.add_listener_local()
+			.global({
+				let registry = Rc::downgrade(&registry);
+				let metadata = Rc::clone(&metadata);
+				let commands = commands.clone();
+				let target = Rc::clone(&target);
+				let target_id = Rc::clone(&target_id);
+				move |global| {
+					if is_default_metadata(global) {
+						let Some(registry) = registry.upgrade() else {
+							return;
+						};
+
+						match registry.bind::<Metadata, _>(global) {
+							Ok(bound) => {
+								let listener = watch_default_sink(&bound, commands.clone(), generation, Rc::clone(&target));
+								*metadata.borrow_mut() = Some((listener, bound));
+							},
+							Err(error) => eprintln!("Not following the default sink: {}", error),
+						}
+
+						return;
+					}
+
+					let Some(node) = Node::from_global(global) else {
+						return;
+					};
+
+					let mut target = target.borrow_mut();
+
+					let Some(current) = target.as_mut().filter(|current| current.name == node.name) else {
+						return;
+					};
+
+					target_id.set(Some(global.id));
+
+					// a node which is removed and added again has a new serial
+					if current.serial != node.serial {
+						*current = node;
+						let _ = commands.send(Command::Refresh(generation));
+					}
+				}
+			})
+			.global_remove({
+				let target_id = Rc::clone(&target_id);
+				move |id| {
+					if target_id.get() == Some(id) {
+						target_id.set(None);
+						// the default sink is captured until the target comes back
+						let _ = commands.send(Command::Refresh(generation));
+					}
+				}
+			})
+			.register();
+
+		Ok(Self {
+			_metadata: metadata,
+			_registry_listener: registry_listener,
+			_registry: registry,
+			_core: core,
+			_context: context,
+		})
+	}
+}
+
+fn is_default_metadata(global: &GlobalObject<ForeignDict>) -> bool {
+	global.type_ == ObjectType::Metadata
+		&& global.props.as_ref().and_then(|properties| properties.get("metadata.name")) == Some("default")
+}
+
+/// Reconnects whenever the default sink changes, unless there is a target.
+fn watch_default_sink(
+	metadata: &Metadata,
+	commands: Sender<Command>,
+	generation: u64,
+	target: Rc<RefCell<Option<Node>>>,
+) -> MetadataListener {
+	// the current value is announced first, only changes after it matter
+	let default_sink = RefCell::new(None::<Option<String>>);
+
+	metadata.add_listener_local()
+		.property(move |_subject, key, _type, value| {
+			if key != Some(DEFAULT_SINK_KEY) {
+				return 0;
+			}
+
+			let value = value.map(String::from);
+			let previous = default_sink.borrow_mut().replace(value.clone());
+
+			let changed = previous.map_or(false, |previous| previous != value);
+
+			if changed && target.borrow().is_none() {
+				let _ = commands.send(Command::Refresh(generation));
+			}
+
+			0
+		})
+		.register()
+}
@@ -1,13 +1,14 @@
 use std::io::{Write, Seek, Cursor};
 
-use pipewire::{spa::{pod::{serialize::*, PropertyFlags}, utils::Id}};
+use pipewire::{spa::{pod::{serialize::*, PropertyFlags, Value, ChoiceValue}, utils::{Id, Choice, ChoiceEnum, ChoiceFlags}}};
 
 type ChannelPosition = libspa_sys::spa_audio_channel;
 
 // TODO: enums for format and ChannelPosition
 
 pub(crate) struct SpaAudioInfoRaw {
-	pub format: libspa_sys::spa_audio_format,
+	/// Acceptable formats with the preferred one first
+	pub formats: Vec<libspa_sys::spa_audio_format>,
 	pub flags: u32,
 	pub rate: u32,
 	pub channels: Vec<Option<ChannelPosition>>,
@@ -16,7 +17,7 @@ pub(crate) struct SpaAudioInfoRaw {
 impl SpaAudioInfoRaw {
 	pub fn empty() -> Self {
 		Self {
-			format: libspa_sys::SPA_AUDIO_FORMAT_UNKNOWN,
+			formats: vec![],
 			flags: 0,
 			rate: 0,
 			channels: vec![]
@@ -54,24 +55,39 @@ impl PodSerialize for SpaAudioInfoRaw {
 			&Id(libspa_sys::SPA_MEDIA_SUBTYPE_raw),
 			PropertyFlags::READONLY,
 		)?;
-		if self.format != libspa_sys::SPA_AUDIO_FORMAT_UNKNOWN {
-			object_serializer.serialize_property(
-				libspa_sys::SPA_FORMAT_AUDIO_format,
-				&Id(self.format),
-				PropertyFlags::READONLY,
-			)?;
+		match self.formats.as_slice() {
+			[] => {},
+			[format] => {
+				object_serializer.serialize_property(
+					libspa_sys::SPA_FORMAT_AUDIO_format,
+					&Id(*format),
+					PropertyFlags::READONLY,
+				)?;
+			},
+			[preferred, ..] => {
+				let choice = Choice(ChoiceFlags::empty(), ChoiceEnum::Enum {
+					default: Id(*preferred),
+					alternatives: self.formats.iter().map(|f| Id(*f)).collect(),
+				});
+
+				object_serializer.serialize_property(
+					libspa_sys::SPA_FORMAT_AUDIO_format,
+					&Value::Choice(ChoiceValue::Id(choice)),
+					PropertyFlags::empty(),
+				)?;
+			},
 		}
 		if self.rate != 0 {
 			object_serializer.serialize_property(
 				libspa_sys::SPA_FORMAT_AUDIO_rate,
-				&Id(self.rate),
+				&(self.rate as i32),
 				PropertyFlags::READONLY,
 			)?;
 		}
 		if !self.channels.is_empty() {
 			object_serializer.serialize_property(
 				libspa_sys::SPA_FORMAT_AUDIO_channels,
-				&Id(self.channels.len() as u32),
+				&(self.channels.len() as i32),
 				PropertyFlags::READONLY,
 			)?;
 
@@ -0,0 +1,270 @@
+#[cfg(feature = "pipewire")]
+use libspa_sys::spa_audio_format;
+
+/// Formats to offer when negotiating a stream, in order of preference.
+#[cfg(feature = "pipewire")]
+pub const PREFERRED_FORMATS: [spa_audio_format; 14] = [
+	libspa_sys::SPA_AUDIO_FORMAT_F32_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_F32P,
+	libspa_sys::SPA_AUDIO_FORMAT_F64_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_F64P,
+	libspa_sys::SPA_AUDIO_FORMAT_S32_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_S32P,
+	libspa_sys::SPA_AUDIO_FORMAT_S24_32_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_S24_32P,
+	libspa_sys::SPA_AUDIO_FORMAT_S24_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_S24P,
+	libspa_sys::SPA_AUDIO_FORMAT_S16_LE,
+	libspa_sys::SPA_AUDIO_FORMAT_S16P,
+	libspa_sys::SPA_AUDIO_FORMAT_U8,
+	libspa_sys::SPA_AUDIO_FORMAT_U8P,
+];
+
+// raw PCM only comes in some of these, the rest are only negotiated by PipeWire
+#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+	U8,
+	S16,
+	/// 24 bits packed into 3 bytes
+	S24,
+	/// 24 bits in the low bits of 4 bytes
+	S24In32,
+	S32,
+	F32,
+	F64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SampleFormat {
+	pub encoding: Encoding,
+	pub big_endian: bool,
+	/// Planar formats have a separate data block for each channel, all others
+	/// interleave the channels in a single block.
+	// only PipeWire delivers planar formats, so only it needs to check
+	#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+	pub planar: bool,
+}
+
+impl SampleFormat {
+	pub const fn interleaved(encoding: Encoding, big_endian: bool) -> Self {
+		Self { encoding, big_endian, planar: false }
+	}
+
+	#[cfg(feature = "pipewire")]
+	const fn planar(encoding: Encoding) -> Self {
+		Self { encoding, big_endian: cfg!(target_endian = "big"), planar: true }
+	}
+
+	#[cfg(feature = "pipewire")]
+	pub fn from_spa(format: spa_audio_format) -> Option<Self> {
+		use Encoding::*;
+
+		Some(match format {
+			libspa_sys::SPA_AUDIO_FORMAT_U8 => Self::interleaved(U8, false),
+			libspa_sys::SPA_AUDIO_FORMAT_S16_LE => Self::interleaved(S16, false),
+			libspa_sys::SPA_AUDIO_FORMAT_S16_BE => Self::interleaved(S16, true),
+			libspa_sys::SPA_AUDIO_FORMAT_S24_LE => Self::interleaved(S24, false),
+			libspa_sys::SPA_AUDIO_FORMAT_S24_BE => Self::interleaved(S24, true),
+			libspa_sys::SPA_AUDIO_FORMAT_S24_32_LE => Self::interleaved(S24In32, false),
+			libspa_sys::SPA_AUDIO_FORMAT_S24_32_BE => Self::interleaved(S24In32, true),
+			libspa_sys::SPA_AUDIO_FORMAT_S32_LE => Self::interleaved(S32, false),
+			libspa_sys::SPA_AUDIO_FORMAT_S32_BE => Self::interleaved(S32, true),
+			libspa_sys::SPA_AUDIO_FORMAT_F32_LE => Self::interleaved(F32, false),
+			libspa_sys::SPA_AUDIO_FORMAT_F32_BE => Self::interleaved(F32, true),
+			libspa_sys::SPA_AUDIO_FORMAT_F64_LE => Self::interleaved(F64, false),
+			libspa_sys::SPA_AUDIO_FORMAT_F64_BE => Self::interleaved(F64, true),
+			libspa_sys::SPA_AUDIO_FORMAT_U8P => Self::planar(U8),
+			libspa_sys::SPA_AUDIO_FORMAT_S16P => Self::planar(S16),
+			libspa_sys::SPA_AUDIO_FORMAT_S24P => Self::planar(S24),
+			libspa_sys::SPA_AUDIO_FORMAT_S24_32P => Self::planar(S24In32),
+			libspa_sys::SPA_AUDIO_FORMAT_S32P => Self::planar(S32),
+			libspa_sys::SPA_AUDIO_FORMAT_F32P => Self::planar(F32),
+			libspa_sys::SPA_AUDIO_FORMAT_F64P => Self::planar(F64),
+			_ => return None,
+		})
+	}
+
+	/// Size of a single sample in bytes.
+	pub fn sample_size(&self) -> usize {
+		match self.encoding {
+			Encoding::U8 => 1,
+			Encoding::S16 => 2,
+			Encoding::S24 => 3,
+			Encoding::S24In32 | Encoding::S32 | Encoding::F32 => 4,
+			Encoding::F64 => 8,
+		}
+	}
+
+	/// Converts raw sample data to floats in the range -1 to 1.
+	/// Any trailing partial sample is ignored.
+	pub fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+		bytes.chunks_exact(self.sample_size())
+			.map(|sample| self.decode_sample(sample))
+			.collect()
+	}
+
+	/// Converts interleaved sample data to a block of floats for each channel.
+	/// Any trailing partial frame is ignored, so every block is the same length.
+	pub fn decode_interleaved(&self, bytes: &[u8], channel_count: usize) -> Vec<Vec<f32>> {
+		let channel_count = channel_count.max(1);
+		let frame_size = self.sample_size() * channel_count;
+		let samples = self.decode(&bytes[..bytes.len() - bytes.len() % frame_size]);
+
+		(0..channel_count)
+			.map(|channel| {
+				samples.iter()
+					.skip(channel)
+					.step_by(channel_count)
+					.copied()
+					.collect()
+			})
+			.collect()
+	}
+
+	fn decode_sample(&self, sample: &[u8]) -> f32 {
+		match self.encoding {
+			Encoding::U8 => (sample[0] as f32 - 128.0) / 128.0,
+			Encoding::S16 => {
+				i16::from_le_bytes(self.little_endian(sample)) as f32 / 32768.0
+			},
+			Encoding::S24 => {
+				let [low, middle, high] = self.little_endian(sample);
+				// shift into the top of an i32 and back down to sign extend
+				let value = i32::from_le_bytes([0, low, middle, high]) >> 8;
+				value as f32 / 8388608.0
+			},
+			Encoding::S24In32 => {
+				let value = i32::from_le_bytes(self.little_endian(sample)) << 8 >> 8;
+				value as f32 / 8388608.0
+			},
+			Encoding::S32 => {
+				i32::from_le_bytes(self.little_endian(sample)) as f32 / 2147483648.0
+			},
+			Encoding::F32 => f32::from_le_bytes(self.little_endian(sample)),
+			Encoding::F64 => f64::from_le_bytes(self.little_endian(sample)) as f32,
+		}
+	}
+
+	fn little_endian<const N: usize>(&self, sample: &[u8]) -> [u8; N] {
+		let mut bytes: [u8; N] = sample.try_into().unwrap();
+
+		if self.big_endian {
+			bytes.reverse();
+		}
+
+		bytes
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn decode(encoding: Encoding, big_endian: bool, bytes: &[u8]) -> Vec<f32> {
+		SampleFormat::interleaved(encoding, big_endian).decode(bytes)
+	}
+
+	/// Decodes `little` as little endian and the same samples with their bytes
+	/// reversed as big endian, which should give the same values.
+	fn assert_decodes(encoding: Encoding, little: &[u8], expected: &[f32]) {
+		let size = SampleFormat::interleaved(encoding, false).sample_size();
+		let big = little.chunks(size)
+			.flat_map(|sample| sample.iter().rev())
+			.copied()
+			.collect::<Vec<_>>();
+
+		assert_eq!(decode(encoding, false, little), expected, "{:?} little endian", encoding);
+		assert_eq!(decode(encoding, true, &big), expected, "{:?} big endian", encoding);
+	}
+
+	#[test]
+	fn u8_is_biased() {
+		assert_decodes(Encoding::U8, &[0x80, 0x00, 0xc0, 0xff], &[0.0, -1.0, 0.5, 127.0 / 128.0]);
+	}
+
+	#[test]
+	fn s16() {
+		assert_decodes(Encoding::S16, &[
+			0x00, 0x00,
+			0x00, 0x40,
+			0x00, 0x80,
+			0xff, 0xff,
+		], &[0.0, 0.5, -1.0, -1.0 / 32768.0]);
+	}
+
+	#[test]
+	fn s24_is_sign_extended() {
+		assert_decodes(Encoding::S24, &[
+			0x00, 0x00, 0x40,
+			0x00, 0x00, 0x80,
+			0xff, 0xff, 0xff,
+			0xff, 0xff, 0x7f,
+		], &[0.5, -1.0, -1.0 / 8388608.0, 8388607.0 / 8388608.0]);
+	}
+
+	#[test]
+	fn s24_in_32_ignores_the_high_byte() {
+		assert_decodes(Encoding::S24In32, &[
+			0x00, 0x00, 0x40, 0x00,
+			0x00, 0x00, 0x80, 0xff,
+			// the unused byte can hold anything
+			0xff, 0xff, 0xff, 0x00,
+			0x00, 0x00, 0x40, 0xff,
+		], &[0.5, -1.0, -1.0 / 8388608.0, 0.5]);
+	}
+
+	#[test]
+	fn s32() {
+		assert_decodes(Encoding::S32, &[
+			0x00, 0x00, 0x00, 0x40,
+			0x00, 0x00, 0x00, 0x80,
+			0x00, 0x00, 0x00, 0x00,
+		], &[0.5, -1.0, 0.0]);
+	}
+
+	#[test]
+	fn f32() {
+		let bytes = [0.25f32, -1.0].iter()
+			.flat_map(|value| value.to_le_bytes())
+			.collect::<Vec<_>>();
+
+		assert_decodes(Encoding::F32, &bytes, &[0.25, -1.0]);
+	}
+
+	#[test]
+	fn f64() {
+		let bytes = [0.25f64, -1.0].iter()
+			.flat_map(|value| value.to_le_bytes())
+			.collect::<Vec<_>>();
+
+		assert_decodes(Encoding::F64, &bytes, &[0.25, -1.0]);
+	}
+
+	#[test]
+	fn partial_sample_is_dropped() {
+		assert_eq!(decode(Encoding::S16, false, &[0x00, 0x40, 0x00]), [0.5]);
+		assert!(decode(Encoding::S24, false, &[0x00, 0x00]).is_empty());
+	}
+
+	#[test]
+	fn interleaved_channels_are_separated() {
+		let format = SampleFormat::interleaved(Encoding::U8, false);
+		let channels = format.decode_interleaved(&[0x80, 0x00, 0xc0, 0x40, 0x80, 0x80], 3);
+
+		assert_eq!(channels, [vec![0.0, -0.5], vec![-1.0, 0.0], vec![0.5, 0.0]]);
+	}
+
+	#[test]
+	fn interleaved_partial_frame_is_dropped() {
+		let format = SampleFormat::interleaved(Encoding::S16, true);
+		// two whole frames, then a whole sample and half of another
+		let channels = format.decode_interleaved(&[
+			0x40, 0x00, 0x80, 0x00,
+			0x00, 0x00, 0xc0, 0x00,
+			0x40, 0x00, 0x40,
+		], 2);
+
+		assert_eq!(channels, [vec![0.5, 0.0], vec![-1.0, -0.5]]);
+	}
+}
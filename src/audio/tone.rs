@@ -0,0 +1,48 @@
+use std::f64::consts::TAU;
+use std::io;
+
+use super::{AudioFormat, AudioReader};
+
+const FORMAT: AudioFormat = AudioFormat { rate: 48000, channels: 2 };
+
+/// Frequencies in hertz and amplitudes of the sine waves making up the tone,
+/// spread across the spectrum so every part of a layout has something to
+/// show.
+const PARTIALS: [(f64, f64); 5] = [
+	(55.0, 0.4),
+	(220.0, 0.25),
+	(880.0, 0.15),
+	(3520.0, 0.1),
+	(14080.0, 0.05),
+];
+
+/// A fixed chord of sine waves which never ends, for snapshots of the
+/// layout and colours without any audio to hand.
+pub(crate) struct Tone {
+	/// frames read so far
+	position: u64,
+}
+
+impl Tone {
+	pub fn new() -> Self {
+		Self { position: 0 }
+	}
+}
+
+impl AudioReader for Tone {
+	fn read(&mut self, frames: usize) -> io::Result<Option<(AudioFormat, Vec<Vec<f32>>)>> {
+		let samples = (self.position..self.position + frames as u64)
+			.map(|frame| {
+				let time = frame as f64 / FORMAT.rate as f64;
+
+				PARTIALS.iter()
+					.map(|(frequency, amplitude)| amplitude * (TAU * frequency * time).sin())
+					.sum::<f64>() as f32
+			})
+			.collect::<Vec<_>>();
+
+		self.position += frames as u64;
+
+		Ok(Some((FORMAT, vec![samples.clone(), samples])))
+	}
+}
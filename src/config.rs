@@ -0,0 +1,402 @@
+use std::{collections::BTreeMap, fmt, io, path::{Path, PathBuf}};
+
+use clap::{Args, ArgMatches, FromArgMatches, builder::RangedU64ValueParser, parser::ValueSource};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::{Colour, ColourScheme, GradientStop, Scroll};
+use crate::visualiser::{Analysis, ChannelMode};
+use crate::layout::{OutputLayout, Shape};
+use crate::window::{Edge, Layer};
+
+/// Every setting which can be given in the config file or on the command line.
+///
+/// Keys in the config file are the names of the command line options without
+/// the leading dashes, e.g. `fft-size = 8192`.
+#[derive(Debug, Clone, PartialEq, Args, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+	/// Shift the spectrum to show more detail at the lower frequencies at
+	/// values greater than 1 and higher frequencies at less than 1.
+	#[arg(short, long, default_value_t = 1.02)]
+	pub power_scale_frequencies: f32,
+	/// Clip the spectrum to have this frequency be the highest pitch
+	#[arg(short, long, default_value_t = 15000.0)]
+	pub ceiling_frequency: f32,
+	/// Clip the spectrum to have this frequency be the lowest pitch
+	#[arg(short, long, default_value_t = 0.0)]
+	pub floor_frequency: f32,
+	/// How to turn the audio into a spectrum
+	#[arg(short, long, value_enum, default_value_t = Analysis::Fft)]
+	pub analysis: Analysis,
+	/// Widen every constant-Q bin by this many hertz, shortening the windows
+	/// of the lowest bins to make them more responsive (variable-Q)
+	#[arg(long, default_value_t = 0.0)]
+	pub bandwidth_offset: f32,
+	/// Number of samples to analyse each frame, the lowest frequencies need
+	/// more samples to resolve while fewer respond faster (a power of 2)
+	#[arg(long, default_value_t = 4096)]
+	pub fft_size: usize,
+	/// Minimum number of new samples between analyses, at most --fft-size
+	#[arg(long, default_value_t = 512)]
+	pub hop: usize,
+	/// Multiply the output levels by the value
+	#[arg(short, long, default_value_t = 1.0)]
+	pub scale: f32,
+	/// Adjust the levels automatically to keep the spectrum near
+	/// --gain-target, on top of --scale
+	#[arg(long)]
+	pub auto_gain: bool,
+	/// Level the automatic gain aims to bring the measured percentile to
+	#[arg(long, default_value_t = 0.7)]
+	pub gain_target: f32,
+	/// Fraction of the spectrum which should be below the target level
+	#[arg(long, default_value_t = 0.95)]
+	pub gain_percentile: f32,
+	/// Time in milliseconds for the automatic gain to rise when the audio gets
+	/// quieter, it falls 8 times faster when the audio gets louder
+	#[arg(long, default_value_t = 3000.0)]
+	pub gain_speed: f32,
+	/// Lowest gain the automatic gain will apply
+	#[arg(long, default_value_t = 0.1)]
+	pub min_gain: f32,
+	/// Highest gain the automatic gain will apply
+	#[arg(long, default_value_t = 20.0)]
+	pub max_gain: f32,
+	/// Levels below this are treated as silence, during which the automatic
+	/// gain returns towards 1
+	#[arg(long, default_value_t = 0.01)]
+	pub noise_floor: f32,
+	/// How to analyse the channels of the audio stream
+	#[arg(long, value_enum, default_value_t = ChannelMode::Mono)]
+	pub channels: ChannelMode,
+	/// Time in milliseconds for levels to rise towards a louder value
+	#[arg(long, default_value_t = 20.0)]
+	pub attack: f32,
+	/// Time in milliseconds for levels to fall towards a quieter value
+	#[arg(long, default_value_t = 150.0)]
+	pub decay: f32,
+	/// Make levels fall with this acceleration (in full heights per second
+	/// squared) instead of decaying
+	#[arg(long, default_value_t = 0.0)]
+	pub gravity: f32,
+	/// Show a marker at the highest recent level, held for this many
+	/// milliseconds before falling (0 disables the markers)
+	#[arg(long, default_value_t = 0.0)]
+	pub peak_hold: f32,
+	/// Acceleration of falling peak markers in full heights per second squared
+	#[arg(long, default_value_t = 2.0)]
+	pub peak_gravity: f32,
+	/// Built-in layout to use when no layout file is given
+	#[arg(long, value_enum, default_value_t = Shape::Fill)]
+	pub shape: Shape,
+	/// Number of bars in the built-in layouts
+	#[arg(long, default_value_t = 64, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+	pub bar_count: usize,
+	/// Fraction of each bar's width to leave empty
+	#[arg(long, default_value_t = 0.2)]
+	pub bar_gap: f32,
+	/// How the spectrum is coloured
+	#[arg(long, value_enum, default_value_t = ColourScheme::Rainbow)]
+	pub colour_scheme: ColourScheme,
+	/// Colours used by the solid and gradient schemes, as #rrggbb, #rrggbbaa,
+	/// #rgb or #rgba optionally followed by @ and a position from 0 to 1
+	#[arg(long, value_delimiter = ',', default_values_t = GradientStop::defaults())]
+	pub colours: Vec<GradientStop>,
+	/// Colour behind the spectrum, the alpha sets the opacity of the window
+	#[arg(long, default_value_t = Colour::TRANSPARENT)]
+	pub background: Colour,
+	/// Draw a scrolling history of the spectrum instead of the current one,
+	/// coloured by level with the colour scheme
+	#[arg(long)]
+	pub spectrogram: bool,
+	/// Number of spectra the spectrogram shows, one is added every frame
+	#[arg(long, default_value_t = 256)]
+	pub spectrogram_length: u32,
+	/// Direction the spectrogram moves in
+	#[arg(long, value_enum, default_value_t = Scroll::Up)]
+	pub scroll: Scroll,
+	/// GLSL or SPIR-V (ending in .spv) vertex shader to use instead of the
+	/// built-in one
+	#[arg(long, value_name = "PATH")]
+	pub vertex_shader: Option<PathBuf>,
+	/// GLSL or SPIR-V (ending in .spv) fragment shader to use instead of the
+	/// built-in one
+	#[arg(long, value_name = "PATH")]
+	pub fragment_shader: Option<PathBuf>,
+	/// Capture from the audio node with this name, description or serial
+	/// instead of the default output
+	#[arg(short, long)]
+	pub target: Option<String>,
+	/// Width of the window when the compositor leaves the size up to us
+	#[arg(long, default_value_t = 600)]
+	pub width: u32,
+	/// Height of the window when the compositor leaves the size up to us
+	#[arg(long, default_value_t = 400)]
+	pub height: u32,
+	/// Run as a layer of the desktop shell instead of a normal window, for
+	/// example as an animated wallpaper
+	#[arg(long, value_enum)]
+	pub layer: Option<Layer>,
+	/// Edges of the output the layer is attached to, it is stretched between
+	/// opposite edges
+	#[arg(long, value_enum, value_delimiter = ',', default_values_t = Edge::ALL)]
+	pub anchor: Vec<Edge>,
+	/// Space between the layer and the edges it is anchored to, as one value
+	/// for every edge or top,right,bottom,left as in CSS
+	#[arg(long, value_delimiter = ',', allow_negative_numbers = true, default_values_t = [0])]
+	pub margin: Vec<i32>,
+	/// Space the layer reserves at its anchored edge so windows are not placed
+	/// over it, -1 to also cover space reserved by panels
+	#[arg(long, allow_negative_numbers = true, default_value_t = 0)]
+	pub exclusive_zone: i32,
+	/// Name of the output to show the layer on (e.g. DP-1) instead of the one
+	/// the compositor picks
+	#[arg(long)]
+	pub output: Option<String>,
+	/// Path to the obj file to use for displaying data
+	pub layout: Option<PathBuf>,
+	/// Layout settings for specific outputs by name, only available in the
+	/// config file
+	#[arg(skip)]
+	pub outputs: BTreeMap<String, OutputLayout>,
+}
+
+impl Default for Config {
+	/// The defaults of the command line options.
+	fn default() -> Self {
+		let matches = Self::command().get_matches_from([env!("CARGO_PKG_NAME")]);
+
+		Self::from_arg_matches(&matches).unwrap()
+	}
+}
+
+impl Config {
+	/// The command line options alone.
+	fn command() -> clap::Command {
+		Self::augment_args(clap::Command::new(env!("CARGO_PKG_NAME")))
+	}
+
+	/// Checks for values which are allowed by their types but make no sense.
+	/// Options from the command line are checked here too, as they end up in
+	/// the same table as the file.
+	fn validate(&self) -> Result<(), String> {
+		if self.fft_size < 2 || !self.fft_size.is_power_of_two() {
+			return Err(format!("fft-size must be a power of 2, not {}", self.fft_size));
+		}
+
+		if self.hop > self.fft_size {
+			return Err(format!(
+				"hop ({}) can't be more than fft-size ({}), samples would be skipped",
+				self.hop, self.fft_size,
+			));
+		}
+
+		if self.bar_count == 0 {
+			return Err("bar-count must be at least 1".to_owned());
+		}
+
+		for (name, output) in &self.outputs {
+			if output.bar_count == Some(0) {
+				return Err(format!("bar-count for output {} must be at least 1", name));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Debug)]
+pub(crate) enum ConfigError {
+	Io(io::Error),
+	Toml(toml::de::Error),
+	Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfigError::Io(error) => write!(f, "{}", error),
+			ConfigError::Toml(error) => write!(f, "{}", error),
+			ConfigError::Invalid(reason) => write!(f, "{}", reason),
+		}
+	}
+}
+
+/// Where the config comes from: a file, overridden by any options given on
+/// the command line.
+pub(crate) struct ConfigSource {
+	path: PathBuf,
+	/// Whether the file was asked for, rather than being the default path
+	required: bool,
+	overrides: toml::Table,
+}
+
+impl ConfigSource {
+	/// Uses `path` or the default config file, with any options from
+	/// `matches` which were given on the command line taking precedence.
+	pub fn new(path: Option<PathBuf>, matches: &ArgMatches, arguments: &Config) -> Self {
+		let arguments: toml::Table = toml::Value::try_from(arguments).unwrap()
+			.try_into().unwrap();
+
+		let command = Config::command();
+
+		// some settings, like the outputs, can't be given on the command line
+		// so have no argument to look up
+		let overrides = arguments.into_iter()
+			.filter(|(key, _)| {
+				let id = key.replace('-', "_");
+
+				command.get_arguments().any(|argument| argument.get_id() == id.as_str())
+					&& matches.value_source(&id) == Some(ValueSource::CommandLine)
+			})
+			.collect();
+
+		Self {
+			required: path.is_some(),
+			path: path.unwrap_or_else(default_path),
+			overrides,
+		}
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Reads the config, falling back to the defaults for anything not set.
+	pub fn load(&self) -> Result<Config, ConfigError> {
+		let mut table = match std::fs::read_to_string(&self.path) {
+			Ok(contents) => contents.parse::<toml::Table>().map_err(ConfigError::Toml)?,
+			Err(error) if error.kind() == io::ErrorKind::NotFound && !self.required => {
+				toml::Table::new()
+			},
+			Err(error) => return Err(ConfigError::Io(error)),
+		};
+
+		table.extend(self.overrides.clone());
+
+		let config: Config = toml::Value::Table(table).try_into().map_err(ConfigError::Toml)?;
+		config.validate().map_err(ConfigError::Invalid)?;
+
+		Ok(config)
+	}
+
+	/// Calls `on_change` with the new config whenever the file changes.
+	/// Changes which fail to load are reported and otherwise ignored.
+	///
+	/// Returns `None` if the directory the file would be in does not exist.
+	/// The watcher stops when it is dropped.
+	pub fn watch(
+		self,
+		mut config: Config,
+		mut on_change: impl FnMut(&Config) + Send + 'static,
+	) -> notify::Result<Option<RecommendedWatcher>> {
+		// editors often replace the file rather than writing to it,
+		// so watch the directory in case the file itself is removed
+		let Some(directory) = self.path.parent().filter(|directory| directory.is_dir()) else {
+			return Ok(None);
+		};
+		let directory = directory.to_owned();
+
+		let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+			let event = match event {
+				Ok(event) => event,
+				Err(error) => return eprintln!("Error watching config: {}", error),
+			};
+
+			let affects_config = !matches!(event.kind, EventKind::Access(_))
+				&& event.paths.iter().any(|path| path.file_name() == self.path.file_name());
+
+			if !affects_config {
+				return;
+			}
+
+			match self.load() {
+				// saving can produce several events for the same change
+				Ok(new_config) if new_config == config => {},
+				Ok(new_config) => {
+					config = new_config;
+					on_change(&config);
+				},
+				Err(error) => eprintln!("Failed to reload {}: {}", self.path.display(), error),
+			}
+		})?;
+
+		watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+		Ok(Some(watcher))
+	}
+}
+
+/// `$XDG_CONFIG_HOME/visualiser/config.toml`, or under `~/.config` if that is
+/// not set.
+fn default_path() -> PathBuf {
+	let config_home = std::env::var_os("XDG_CONFIG_HOME")
+		.map(PathBuf::from)
+		.filter(|path| path.is_absolute())
+		.unwrap_or_else(|| {
+			let home = std::env::var_os("HOME").unwrap_or_default();
+			PathBuf::from(home).join(".config")
+		});
+
+	config_home.join(env!("CARGO_PKG_NAME")).join("config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_are_valid() {
+		assert_eq!(Config::default().validate(), Ok(()));
+	}
+
+	#[test]
+	fn fft_size_is_a_power_of_two() {
+		for fft_size in [0, 1, 1000, 4097] {
+			let config = Config { fft_size, hop: 0, ..Config::default() };
+			assert!(config.validate().is_err(), "{} was accepted", fft_size);
+		}
+
+		let config = Config { fft_size: 1024, hop: 1024, ..Config::default() };
+		assert_eq!(config.validate(), Ok(()));
+	}
+
+	#[test]
+	fn hop_is_at_most_fft_size() {
+		let config = Config { fft_size: 1024, hop: 1025, ..Config::default() };
+		assert!(config.validate().is_err());
+	}
+
+	#[test]
+	fn bar_count_is_at_least_one() {
+		let config = Config { bar_count: 0, ..Config::default() };
+		assert!(config.validate().is_err());
+
+		let mut config = Config::default();
+		config.outputs.insert("DP-1".to_owned(), OutputLayout { bar_count: Some(0), ..OutputLayout::default() });
+		assert!(config.validate().is_err());
+	}
+
+	/// The source for `arguments` given as the command line.
+	fn source(arguments: &[&str]) -> ConfigSource {
+		let matches = Config::command().get_matches_from(arguments);
+		let config = Config::from_arg_matches(&matches).unwrap();
+
+		ConfigSource::new(None, &matches, &config)
+	}
+
+	#[test]
+	fn only_options_given_override_the_file() {
+		let source = source(&["visualiser", "--bar-count", "12", "--shape", "bars"]);
+
+		let mut keys = source.overrides.keys().collect::<Vec<_>>();
+		keys.sort();
+		assert_eq!(keys, ["bar-count", "shape"]);
+	}
+
+	#[test]
+	fn no_options_override_nothing() {
+		assert!(source(&["visualiser"]).overrides.is_empty());
+	}
+}
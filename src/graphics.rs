@@ -1,18 +1,32 @@
+use std::fmt;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use wayland_client::protocol::wl_display::WlDisplay;
 use wayland_client::protocol::wl_surface::WlSurface;
 
-use vulkano::{instance::*, sync::{self, FlushError}};
+use vulkano::{instance::*, sync::{self, FlushError}, swapchain::AcquireError};
 use vulkano::shader::*;
 
-use vulkano::VulkanLibrary;
+use vulkano::{LoadingError, VulkanLibrary};
+use vulkano::format::Format;
+use vulkano::swapchain::CompositeAlpha;
 
 use vulkano::sync::GpuFuture;
 
-use crate::BUFFER_SIZE;
+use crate::config::Config;
+use crate::visualiser::Frame;
 
-use self::{surface::Surface, swapchain::Swapchain, vertex::{VisualiserVertex,VisualiserVertexVec}, sampler::Sampler, device::Device};
+use self::{surface::Surface, swapchain::Swapchain, resources::Resources, device::Device};
+use self::colours::ColourUniform;
+use self::pipeline::{Pipeline, PipelineError};
+
+pub(crate) use self::vertex::{VisualiserVertex, VisualiserVertexVec};
+pub(crate) use self::colours::{Colour, ColourScheme, GradientStop};
+pub(crate) use self::shaders::{ShaderCode, ShaderError};
+pub(crate) use self::spectrogram::Scroll;
+pub(crate) use self::offscreen::{Offscreen, OffscreenError, RenderError, Snapshot};
+pub(crate) use self::surface::SurfaceError;
 
 mod swapchain;
 mod surface;
@@ -21,6 +35,11 @@ mod device;
 
 mod vertex;
 mod sampler;
+mod resources;
+mod colours;
+mod spectrogram;
+mod offscreen;
+pub(crate) mod shaders;
 
 const INSTANCE_EXTENSIONS: InstanceExtensions = InstanceExtensions {
 	khr_surface: true,
@@ -28,100 +47,364 @@ const INSTANCE_EXTENSIONS: InstanceExtensions = InstanceExtensions {
 	.. InstanceExtensions::empty()
 };
 
+/// How many times to recreate an out of date swapchain in a single draw.
+const PRESENT_ATTEMPTS: usize = 3;
+
+/// What became of a frame given to `Graphics::present`.
+enum Presentation {
+	Presented,
+	/// nothing was presented, the swapchain has to be recreated first
+	OutOfDate,
+	/// nothing was presented and the error has been logged
+	Failed,
+}
+
+/// Why Vulkan couldn't be set up.
+#[derive(Debug)]
+pub(crate) enum InstanceError {
+	Library(LoadingError),
+	Instance(InstanceCreationError),
+}
+
+impl fmt::Display for InstanceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			InstanceError::Library(error) => write!(f, "Failed to load vulkan library: {}", error),
+			InstanceError::Instance(error) => write!(f, "Couldn't build instance: {}", error),
+		}
+	}
+}
+
+/// The Vulkan instance and device shared by every surface.
+pub(crate) struct GraphicsContext {
+	instance: Arc<Instance>,
+	/// created for the first surface, as it depends on what can present to it
+	device: Option<Rc<Device>>,
+}
+
+impl GraphicsContext {
+	pub fn new() -> Self {
+		Self {
+			instance: Graphics::instance(INSTANCE_EXTENSIONS),
+			device: None,
+		}
+	}
+}
+
 pub(crate) struct Graphics {
 	instance: Arc<Instance>,
-	device: Device,
+	device: Rc<Device>,
 	surface: Surface,
 	swapchain: Swapchain,
-	visualiser_sampler: Sampler,
+	resources: Resources,
+	vertices: VisualiserVertexVec,
+	extent: [u32; 2],
+	specializations: ShaderSpecializations,
+	/// the shaders the pipeline was built from, kept to rebuild it
+	shaders: ShaderCode,
+	/// set when the swapchain no longer matches the surface
+	recreate_swapchain: bool,
 	previous_frame_future: Option<Box<dyn GpuFuture>>,
 }
 
-struct ShaderSpecializations {}
+/// A pipeline built for a [`Graphics`] from new shaders, which it can switch
+/// to without anything left to fail.
+pub(crate) struct PreparedShaders {
+	shaders: ShaderCode,
+	pipeline: Pipeline,
+}
+
+/// The specialization constants given to both shaders, in `constant_id`
+/// order.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct ShaderSpecializations {
+	scheme: u32,
+	stop_count: u32,
+	/// a `VkBool32`
+	premultiplied: u32,
+	/// a `VkBool32`
+	spectrogram: u32,
+	scroll: u32,
+}
+
+impl ShaderSpecializations {
+	fn new(config: &Config, premultiplied: bool) -> Self {
+		Self {
+			scheme: config.colour_scheme as u32,
+			stop_count: config.colours.len().clamp(1, colours::MAX_STOPS) as u32,
+			premultiplied: premultiplied as u32,
+			spectrogram: config.spectrogram as u32,
+			scroll: config.scroll as u32,
+		}
+	}
+}
+
 unsafe impl SpecializationConstants for ShaderSpecializations {
     fn descriptors() -> &'static [SpecializationMapEntry] {
-		static DESCRIPTORS: [SpecializationMapEntry; 0] = [];
+		static DESCRIPTORS: [SpecializationMapEntry; 5] = [
+			SpecializationMapEntry { constant_id: 0, offset: 0, size: 4 },
+			SpecializationMapEntry { constant_id: 1, offset: 4, size: 4 },
+			SpecializationMapEntry { constant_id: 2, offset: 8, size: 4 },
+			SpecializationMapEntry { constant_id: 3, offset: 12, size: 4 },
+			SpecializationMapEntry { constant_id: 4, offset: 16, size: 4 },
+		];
 
 		&DESCRIPTORS
     }
 }
 
+/// Builds a pipeline from `shaders`, or from the built-in shaders if those
+/// can't be used. Returns the pipeline and the shaders it was built from.
+fn initial_pipeline(
+	device: &Device,
+	format: Format,
+	shaders: &ShaderCode,
+	specializations: ShaderSpecializations,
+) -> (Pipeline, ShaderCode) {
+	match Pipeline::new(device.into(), format, shaders, specializations) {
+		Ok(pipeline) => (pipeline, shaders.clone()),
+		Err(error) => {
+			eprintln!("Failed to build pipeline, using the built-in shaders: {}", error);
+
+			let shaders = ShaderCode::builtin();
+			let pipeline = Pipeline::new(device.into(), format, &shaders, specializations).unwrap();
+
+			(pipeline, shaders)
+		},
+	}
+}
+
 impl Graphics {
-	fn instance() -> Arc<Instance> {
-		let library = VulkanLibrary::new()
-			.expect("Failed to load vulkan library");
+	fn instance(enabled_extensions: InstanceExtensions) -> Arc<Instance> {
+		Self::try_instance(enabled_extensions).unwrap_or_else(|error| panic!("{}", error))
+	}
+
+	fn try_instance(enabled_extensions: InstanceExtensions) -> Result<Arc<Instance>, InstanceError> {
+		let library = VulkanLibrary::new().map_err(InstanceError::Library)?;
 
 		Instance::new(
 			library,
 			InstanceCreateInfo {
-				enabled_extensions: INSTANCE_EXTENSIONS,
+				enabled_extensions,
 				.. Default::default()
 			}
-		).expect("Couldn't build instance")
+		).map_err(InstanceError::Instance)
 	}
 
 	pub fn new(
+		context: &mut GraphicsContext,
 		display: &WlDisplay,
 		surface: &WlSurface,
+		vertices: &VisualiserVertexVec,
 		extent: [u32; 2],
-	) -> Self {
-		let instance = Self::instance();
+		config: &Config,
+		shaders: &ShaderCode,
+	) -> Result<Self, SurfaceError> {
+		let instance = Arc::clone(&context.instance);
+
+		let (surface, device) = Surface::from_wayland(
+			Arc::clone(&instance),
+			display,
+			surface,
+			context.device.clone(),
+		)?;
 
-		let (surface, device) = Surface::from_wayland(Arc::clone(&instance), display, surface);
+		context.device = Some(Rc::clone(&device));
 		
-		let visualiser_sampler = Sampler::new(&device);
+		let srgb_target = Self::srgb_target(&surface);
+		let premultiplied = Self::premultiplied(&surface);
 
-		let mut vertices = VisualiserVertexVec::with_capacity(6);
+		let resources = Resources::new(
+			&device,
+			ColourUniform::new(config, srgb_target, premultiplied),
+			config.spectrogram_length,
+		);
 
-		vertices.push(VisualiserVertex { position: [-1.0, -1.0], frequency: 0.0, amplitude: 1.0 });
-		vertices.push(VisualiserVertex { position: [1.0, -1.0], frequency: 1.0, amplitude: 1.0 });
-		vertices.push(VisualiserVertex { position: [1.0, 1.0], frequency: 1.0, amplitude: 0.0 });
-		vertices.push(VisualiserVertex { position: [-1.0, -1.0], frequency: 0.0, amplitude: 1.0 });
-		vertices.push(VisualiserVertex { position: [-1.0, 1.0], frequency: 0.0, amplitude: 0.0 });
-		vertices.push(VisualiserVertex { position: [1.0, 1.0], frequency: 1.0, amplitude: 0.0 });
-		
-		let swapchain = Swapchain::new(&device, &surface, &visualiser_sampler, &vertices, extent);
+		let specializations = ShaderSpecializations::new(config, premultiplied);
+
+		let (pipeline, shaders) = initial_pipeline(&device, surface.format, shaders, specializations);
 
-		let previous_frame_future = Some(sync::now((&device).into()).boxed());
+		let swapchain = Swapchain::new(&device, &surface, &resources, vertices, extent, pipeline);
 
-		Graphics {
+		let previous_frame_future = Some(sync::now((&*device).into()).boxed());
+
+		Ok(Graphics {
 			instance,
 			device,
 			surface,
 			swapchain,
 			previous_frame_future,
-			visualiser_sampler,
+			resources,
+			vertices: vertices.clone(),
+			extent,
+			specializations,
+			shaders,
+			recreate_swapchain: false,
+		})
+	}
+
+	/// Colours are given in sRGB, so need converting when the surface will
+	/// convert them back.
+	fn srgb_target(surface: &Surface) -> bool {
+		matches!(surface.format, Format::B8G8R8A8_SRGB | Format::R8G8B8A8_SRGB)
+	}
+
+	fn premultiplied(surface: &Surface) -> bool {
+		surface.alpha_mode == CompositeAlpha::PreMultiplied
+	}
+
+	pub fn set_layout(&mut self, vertices: &VisualiserVertexVec) {
+		self.vertices = vertices.clone();
+		self.swapchain.record(&self.device, &self.resources, &self.vertices);
+	}
+
+	/// Applies the colour and spectrogram settings from `config`.
+	pub fn set_config(&mut self, config: &Config) {
+		let premultiplied = Self::premultiplied(&self.surface);
+		let colours = ColourUniform::new(config, Self::srgb_target(&self.surface), premultiplied);
+
+		// the background is the clear value recorded in the command buffers
+		let mut record = colours.background() != self.resources.background();
+
+		self.resources.set_colours(colours);
+
+		let spectrogram_length = config.spectrogram_length.max(1);
+
+		if spectrogram_length != self.resources.spectrogram_length() {
+			self.resources.set_spectrogram_length(&self.device, spectrogram_length);
+			record = true;
+		}
+
+		let specializations = ShaderSpecializations::new(config, premultiplied);
+
+		if specializations != self.specializations {
+			match self.build_pipeline(&self.shaders, specializations) {
+				Ok(pipeline) => {
+					self.specializations = specializations;
+					self.swapchain.set_pipeline(&self.device, pipeline, &self.resources, &self.vertices);
+					record = false;
+				},
+				Err(error) => eprintln!("Failed to change colour scheme: {}", error),
+			}
+		}
+
+		if record {
+			self.swapchain.record(&self.device, &self.resources, &self.vertices);
 		}
 	}
 
-	pub fn draw(&mut self, buffer: Option<Box<[f32; BUFFER_SIZE]>>) {
-		let mut previous_future = self.previous_frame_future.take()
-			.unwrap_or_else(|| sync::now((&self.device).into()).boxed());
+	/// Builds a pipeline from `shaders` without using it yet, so several
+	/// surfaces can all be checked before any of them changes.
+	pub fn prepare_shaders(&self, shaders: &ShaderCode) -> Result<PreparedShaders, PipelineError> {
+		let pipeline = self.build_pipeline(shaders, self.specializations)?;
 
-		previous_future.cleanup_finished();
+		Ok(PreparedShaders { shaders: shaders.clone(), pipeline })
+	}
+
+	/// Swaps in a pipeline from `prepare_shaders`.
+	pub fn set_shaders(&mut self, prepared: PreparedShaders) {
+		self.shaders = prepared.shaders;
+		self.swapchain.set_pipeline(&self.device, prepared.pipeline, &self.resources, &self.vertices);
+	}
+
+	fn build_pipeline(
+		&self,
+		shaders: &ShaderCode,
+		specializations: ShaderSpecializations,
+	) -> Result<Pipeline, PipelineError> {
+		Pipeline::new((&*self.device).into(), self.surface.format, shaders, specializations)
+	}
+
+	pub fn resize(&mut self, extent: [u32; 2]) {
+		if extent == self.extent {
+			return;
+		}
+
+		self.extent = extent;
+		self.recreate_swapchain = true;
+	}
+
+	fn recreate_swapchain(&mut self) {
+		match self.swapchain.resize(&self.device, &self.resources, &self.vertices, self.extent) {
+			Ok(()) => self.recreate_swapchain = false,
+			Err(error) => eprintln!("Failed to recreate swapchain: {}", error),
+		}
+	}
+
+	/// Returns false if nothing could be presented, in which case the surface
+	/// has to be committed for the next frame callback to come.
+	pub fn draw(&mut self, frame: Option<&Frame>) -> bool {
+		self.resources.write_colours();
 
 		// If data is none, we don't need to update the surface.
 		// However, wayland will not send the next frame callback until we do.
 		// So, we draw anyway.
-		if let Some(data) = buffer {
-			match self.visualiser_sampler.buffer.write() {
-				Ok(mut visualiser) => visualiser.copy_from_slice(data.as_slice()),
-				Err(_) => {
-					self.previous_frame_future = Some(previous_future);
-					// if we can't change the buffer then the frame would be the same
-					return;
-				}
+		if let Some(frame) = frame {
+			// the buffers still hold the previous frame, which is presented
+			// again rather than nothing at all
+			if let Err(error) = self.resources.write(frame, self.extent) {
+				eprintln!("Failed to update frame, showing the previous one: {}", error);
 			}
 		}
 
-		let (acquire_future, present_info, command_buffer) = self.swapchain.next();
+		// something has to be presented for the next frame callback to come,
+		// so try again with a new swapchain if the old one is out of date
+		for _ in 0..PRESENT_ATTEMPTS {
+			if self.recreate_swapchain {
+				self.recreate_swapchain();
+			}
 
-		let future = previous_future
-			.join(acquire_future)
-			.then_execute(
-				Arc::clone(&self.device.queue),
-				command_buffer,
-			).unwrap()
+			match self.present() {
+				Presentation::Presented => return true,
+				Presentation::Failed => return false,
+				Presentation::OutOfDate => self.recreate_swapchain = true,
+			}
+		}
+
+		eprintln!("Swapchain still out of date after {} attempts, skipping frame", PRESENT_ATTEMPTS);
+		false
+	}
+
+	fn present(&mut self) -> Presentation {
+		let mut previous_future = self.previous_frame_future.take()
+			.unwrap_or_else(|| sync::now((&*self.device).into()).boxed());
+
+		previous_future.cleanup_finished();
+
+		let (acquire_future, present_info, command_buffer, suboptimal) = match self.swapchain.next() {
+			Ok(next) => next,
+			Err(AcquireError::OutOfDate) => {
+				self.previous_frame_future = Some(previous_future);
+				return Presentation::OutOfDate;
+			},
+			Err(error) => {
+				eprintln!("Failed to acquire swapchain image: {}", error);
+				self.previous_frame_future = Some(previous_future);
+				return Presentation::Failed;
+			},
+		};
+
+		// a suboptimal swapchain still works so use it for this frame
+		if suboptimal {
+			self.recreate_swapchain = true;
+		}
+
+		let mut future = previous_future.join(acquire_future).boxed();
+
+		if let Some(uploads) = self.resources.frame_uploads(&self.device) {
+			future = match future.then_execute(Arc::clone(&self.device.queue), uploads) {
+				Ok(future) => future.boxed(),
+				Err(error) => return self.drop_frame(error),
+			};
+		}
+
+		let future = match future.then_execute(Arc::clone(&self.device.queue), command_buffer) {
+			Ok(future) => future,
+			Err(error) => return self.drop_frame(error),
+		};
+
+		let future = future
 			.then_swapchain_present(
 				Arc::clone(&self.device.queue),
 				present_info,
@@ -131,14 +414,20 @@ impl Graphics {
 		match future {
 			Ok(future) => {
 				self.previous_frame_future = Some(future.boxed());
+				Presentation::Presented
 			},
 			Err(FlushError::OutOfDate) => {
-				todo!()
+				self.previous_frame_future = Some(sync::now((&*self.device).into()).boxed());
+				Presentation::OutOfDate
 			},
-			Err(e) => {
-				todo!("{:?}", e)
-			}
+			Err(error) => self.drop_frame(error),
 		}
 	}
-}
 
+	/// Logs why a frame couldn't be presented and starts the next one afresh.
+	fn drop_frame(&mut self, error: impl fmt::Display) -> Presentation {
+		eprintln!("Failed to present frame: {}", error);
+		self.previous_frame_future = Some(sync::now((&*self.device).into()).boxed());
+		Presentation::Failed
+	}
+}
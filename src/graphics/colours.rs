@@ -0,0 +1,316 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// The most gradient stops the shader supports, matching `MAX_STOPS` in
+/// `basic.frag`.
+pub const MAX_STOPS: usize = 16;
+
+/// How fragments are coloured, the value is the `SCHEME` constant in the
+/// fragment shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ColourScheme {
+	/// A hue for every frequency
+	Rainbow = 0,
+	/// The first colour everywhere
+	Solid = 1,
+	/// A gradient from the lowest to the highest frequency
+	Frequency = 2,
+	/// A gradient from the bottom to the top of each bar
+	Amplitude = 3,
+}
+
+/// An sRGB colour with straight alpha, written as `#rrggbb` or `#rrggbbaa`, or
+/// `#rgb` or `#rgba` as in CSS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Colour(pub [f32; 4]);
+
+impl Colour {
+	pub const TRANSPARENT: Colour = Colour([0.0; 4]);
+
+	/// The colour as the shader should output it, converted to linear light
+	/// for sRGB targets and multiplied by its alpha if the compositor expects
+	/// that.
+	pub fn to_output(self, srgb_target: bool, premultiplied: bool) -> [f32; 4] {
+		let [red, green, blue, alpha] = self.0;
+
+		let linear = |value: f32| if !srgb_target {
+			value
+		} else if value <= 0.04045 {
+			value / 12.92
+		} else {
+			((value + 0.055) / 1.055).powf(2.4)
+		};
+
+		let scale = if premultiplied { alpha } else { 1.0 };
+
+		[linear(red) * scale, linear(green) * scale, linear(blue) * scale, alpha]
+	}
+}
+
+impl FromStr for Colour {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		let hex = value.strip_prefix('#').unwrap_or(value);
+
+		if !matches!(hex.len(), 3 | 4 | 6 | 8) || !hex.is_ascii() {
+			return Err(format!("{:?} is not a colour, expected #rrggbb, #rrggbbaa, #rgb or #rgba", value));
+		}
+
+		// each digit of the short forms stands for two of the same
+		let hex = match hex.len() {
+			3 | 4 => hex.chars().flat_map(|digit| [digit, digit]).collect(),
+			_ => hex.to_owned(),
+		};
+
+		let mut channels = [1.0; 4];
+
+		for (channel, digits) in channels.iter_mut().zip(hex.as_bytes().chunks(2)) {
+			let digits = std::str::from_utf8(digits).unwrap();
+			let byte = u8::from_str_radix(digits, 16)
+				.map_err(|_| format!("{:?} is not a hexadecimal colour", value))?;
+
+			*channel = byte as f32 / 255.0;
+		}
+
+		Ok(Colour(channels))
+	}
+}
+
+impl fmt::Display for Colour {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let [red, green, blue, alpha] = self.0.map(|channel| (channel * 255.0).round() as u8);
+
+		write!(f, "#{:02x}{:02x}{:02x}", red, green, blue)?;
+
+		if alpha != 255 {
+			write!(f, "{:02x}", alpha)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl TryFrom<String> for Colour {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl From<Colour> for String {
+	fn from(colour: Colour) -> Self {
+		colour.to_string()
+	}
+}
+
+/// A colour in a gradient, written as the colour optionally followed by `@`
+/// and its position from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct GradientStop {
+	pub colour: Colour,
+	/// stops without a position are spaced evenly between their neighbours
+	pub position: Option<f32>,
+}
+
+impl GradientStop {
+	pub fn defaults() -> Vec<GradientStop> {
+		["#3f5efb", "#fc466b"].into_iter()
+			.map(|colour| colour.parse().unwrap())
+			.collect()
+	}
+}
+
+impl FromStr for GradientStop {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		let (colour, position) = match value.split_once('@') {
+			Some((colour, position)) => {
+				let position = position.trim().parse::<f32>()
+					.map_err(|_| format!("{:?} is not a gradient position", position))?;
+
+				(colour, Some(position))
+			},
+			None => (value, None),
+		};
+
+		Ok(GradientStop { colour: colour.trim().parse()?, position })
+	}
+}
+
+impl fmt::Display for GradientStop {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.position {
+			Some(position) => write!(f, "{}@{}", self.colour, position),
+			None => write!(f, "{}", self.colour),
+		}
+	}
+}
+
+impl TryFrom<String> for GradientStop {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl From<GradientStop> for String {
+	fn from(stop: GradientStop) -> Self {
+		stop.to_string()
+	}
+}
+
+/// Fills in the positions missing from `stops`, the first and last default to
+/// 0 and 1 and the rest are spread evenly between the stops either side.
+fn positions(stops: &[GradientStop]) -> Vec<f32> {
+	let mut positions = stops.iter().map(|stop| stop.position).collect::<Vec<_>>();
+
+	if let Some(first) = positions.first_mut() {
+		first.get_or_insert(0.0);
+	}
+
+	if let Some(last) = positions.last_mut() {
+		last.get_or_insert(1.0);
+	}
+
+	let mut start = 0;
+
+	for end in 1..positions.len() {
+		if let Some(end_position) = positions[end] {
+			let start_position = positions[start].unwrap();
+
+			for (i, between) in positions[(start + 1)..end].iter_mut().enumerate() {
+				let t = (i + 1) as f32 / (end - start) as f32;
+				*between = Some(start_position + (end_position - start_position) * t);
+			}
+
+			start = end;
+		}
+	}
+
+	positions.into_iter().map(Option::unwrap).collect()
+}
+
+/// The `Colours` uniform block in `basic.frag`, laid out for std140.
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct ColourUniform {
+	background: [f32; 4],
+	stop_colours: [[f32; 4]; MAX_STOPS],
+	/// only the first component is used, arrays of scalars are padded to 16
+	/// bytes anyway
+	stop_positions: [[f32; 4]; MAX_STOPS],
+}
+
+impl ColourUniform {
+	pub fn new(config: &Config, srgb_target: bool, premultiplied: bool) -> Self {
+		let stops = &config.colours[..config.colours.len().min(MAX_STOPS)];
+
+		let mut uniform = Self {
+			background: config.background.to_output(srgb_target, premultiplied),
+			stop_colours: [[0.0; 4]; MAX_STOPS],
+			stop_positions: [[0.0; 4]; MAX_STOPS],
+		};
+
+		for (i, (stop, position)) in stops.iter().zip(positions(stops)).enumerate() {
+			// premultiplying happens after interpolating in the shader
+			uniform.stop_colours[i] = stop.colour.to_output(srgb_target, false);
+			uniform.stop_positions[i][0] = position;
+		}
+
+		uniform
+	}
+
+	pub fn background(&self) -> [f32; 4] {
+		self.background
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn colour(value: &str) -> [u8; 4] {
+		value.parse::<Colour>().unwrap().0.map(|channel| (channel * 255.0).round() as u8)
+	}
+
+	fn stop(value: &str, position: Option<f32>) -> GradientStop {
+		GradientStop { colour: value.parse().unwrap(), position }
+	}
+
+	#[test]
+	fn colour_forms() {
+		assert_eq!(colour("#ff8000"), [0xff, 0x80, 0x00, 0xff]);
+		assert_eq!(colour("#ff800040"), [0xff, 0x80, 0x00, 0x40]);
+		assert_eq!(colour("#f80"), [0xff, 0x88, 0x00, 0xff]);
+		assert_eq!(colour("#f804"), [0xff, 0x88, 0x00, 0x44]);
+		// the # can be left out
+		assert_eq!(colour("00FF00"), [0x00, 0xff, 0x00, 0xff]);
+	}
+
+	#[test]
+	fn invalid_colours() {
+		for value in ["", "#", "#ff", "#ff800", "#ff80000", "#gg8000", "#ff8000ff00", "#ff80\u{e9}"] {
+			assert!(value.parse::<Colour>().is_err(), "{:?} was accepted", value);
+		}
+	}
+
+	#[test]
+	fn colours_survive_being_written_out() {
+		for value in ["#ff8000", "#ff800040"] {
+			assert_eq!(value.parse::<Colour>().unwrap().to_string(), value);
+		}
+	}
+
+	#[test]
+	fn gradient_stops() {
+		assert_eq!("#ff8000".parse::<GradientStop>(), Ok(stop("#ff8000", None)));
+		assert_eq!("#ff8000@0.25".parse::<GradientStop>(), Ok(stop("#ff8000", Some(0.25))));
+		assert_eq!(" #f80 @ 0.5".parse::<GradientStop>(), Ok(stop("#f80", Some(0.5))));
+
+		assert!("#ff8000@".parse::<GradientStop>().is_err());
+		assert!("#ff8000@half".parse::<GradientStop>().is_err());
+		assert!("nope@0.5".parse::<GradientStop>().is_err());
+	}
+
+	#[test]
+	fn missing_positions_are_spread_between_their_neighbours() {
+		let stops = [
+			stop("#000", None),
+			stop("#111", None),
+			stop("#222", Some(0.6)),
+			stop("#333", None),
+			stop("#444", None),
+		];
+
+		let positions = positions(&stops);
+		let expected = [0.0, 0.3, 0.6, 0.8, 1.0];
+
+		for (position, expected) in positions.iter().zip(expected) {
+			assert!((position - expected).abs() < 1e-6, "{:?} is not {:?}", positions, expected);
+		}
+	}
+
+	#[test]
+	fn given_positions_are_kept() {
+		let stops = [stop("#000", Some(0.2)), stop("#fff", Some(0.4))];
+		assert_eq!(positions(&stops), [0.2, 0.4]);
+
+		assert_eq!(positions(&[stop("#000", None)]), [0.0]);
+		assert!(positions(&[]).is_empty());
+	}
+}
@@ -24,10 +24,17 @@ const DEVICE_EXTENSIONS: DeviceExtensions = DeviceExtensions {
 };
 
 impl Device {
-	pub fn new(instance: &Arc<Instance>, surface: &Arc<Surface>) -> Self {
+	/// Picks a device which can present to `surface`, or any device which can
+	/// draw if there is no surface.
+	pub fn new(instance: &Arc<Instance>, surface: Option<&Arc<Surface>>) -> Self {
 		let (physical_device, queue_family_index) = Self::choose_device(instance, surface)
 			.expect("No suitable graphics device");
 
+		let enabled_extensions = match surface {
+			Some(_) => DEVICE_EXTENSIONS,
+			None => DeviceExtensions::empty(),
+		};
+
 		let (device, mut queues) = VkDevice::new(
 			physical_device,
 			DeviceCreateInfo {
@@ -35,7 +42,7 @@ impl Device {
 					queue_family_index,
 					..Default::default()
 				}],
-				enabled_extensions: DEVICE_EXTENSIONS,
+				enabled_extensions,
 				enabled_features: Features {
 					dynamic_rendering: true,
 					..Features::empty()
@@ -62,18 +69,20 @@ impl Device {
 
 	pub fn choose_device(
 		instance: &Arc<Instance>,
-		surface: &Arc<Surface>,
+		surface: Option<&Arc<Surface>>,
 	) -> Option<(Arc<PhysicalDevice>, u32)> {
 		instance.enumerate_physical_devices()
 			.unwrap()
-			.filter(|device| device.supported_extensions().contains(&DEVICE_EXTENSIONS))
+			.filter(|device| surface.is_none() || device.supported_extensions().contains(&DEVICE_EXTENSIONS))
 			.filter_map(|device| {
 				device.queue_family_properties()
 					.iter()
 					.enumerate()
 					.position(|(i, queue_properties)| {
 						let supports_graphics = queue_properties.queue_flags.graphics;
-						let supports_surface = device.surface_support(i as u32, surface).unwrap_or(false);
+						let supports_surface = surface.map_or(true, |surface| {
+							device.surface_support(i as u32, surface).unwrap_or(false)
+						});
 						
 						supports_graphics && supports_surface
 					})
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::cpu_access::{ReadLockError, WriteLockError};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferExecError, CommandBufferUsage, CopyImageToBufferInfo, PrimaryAutoCommandBuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
+use vulkano::format::Format;
+use vulkano::image::{AttachmentImage, ImageUsage, view::ImageView};
+use vulkano::instance::InstanceExtensions;
+use vulkano::pipeline::graphics::viewport::Viewport;
+use vulkano::sync::{self, FlushError, GpuFuture};
+
+use crate::config::Config;
+use crate::visualiser::Frame;
+
+use super::colours::ColourUniform;
+use super::device::Device;
+use super::resources::Resources;
+use super::shaders::ShaderCode;
+use super::vertex::VisualiserVertexVec;
+use super::{initial_pipeline, Graphics, InstanceError, ShaderSpecializations};
+
+/// Stored as sRGB so the pixels can be written out as they are.
+const FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// Renders into an image in memory instead of a surface, so it needs no
+/// compositor and works on any Vulkan device, including software rasterisers
+/// such as lavapipe.
+pub(crate) struct Offscreen {
+	device: Device,
+	resources: Resources,
+	/// the rendered image is copied here to be read back
+	buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+	command_buffer: Arc<PrimaryAutoCommandBuffer<StandardCommandBufferAlloc>>,
+	extent: [u32; 2],
+}
+
+/// Why there is nothing to render offscreen with.
+#[derive(Debug)]
+pub(crate) enum OffscreenError {
+	Instance(InstanceError),
+	NoDevice,
+}
+
+impl fmt::Display for OffscreenError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			OffscreenError::Instance(error) => write!(f, "{}", error),
+			OffscreenError::NoDevice => write!(f, "No suitable graphics device"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub(crate) enum RenderError {
+	/// the frame's buffers are still in use
+	Write(WriteLockError),
+	Execute(CommandBufferExecError),
+	Flush(FlushError),
+	/// the rendered image is still being written
+	Read(ReadLockError),
+}
+
+impl fmt::Display for RenderError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RenderError::Write(error) => write!(f, "{}", error),
+			RenderError::Execute(error) => write!(f, "{}", error),
+			RenderError::Flush(error) => write!(f, "{}", error),
+			RenderError::Read(error) => write!(f, "{}", error),
+		}
+	}
+}
+
+/// A rendered frame as straight alpha sRGB pixels, row by row.
+pub(crate) struct Snapshot {
+	pub extent: [u32; 2],
+	pub pixels: Vec<u8>,
+}
+
+impl Offscreen {
+	pub fn new(
+		config: &Config,
+		vertices: &VisualiserVertexVec,
+		extent: [u32; 2],
+		shaders: &ShaderCode,
+	) -> Result<Self, OffscreenError> {
+		let instance = Graphics::try_instance(InstanceExtensions::empty())
+			.map_err(OffscreenError::Instance)?;
+
+		if Device::choose_device(&instance, None).is_none() {
+			return Err(OffscreenError::NoDevice);
+		}
+
+		let device = Device::new(&instance, None);
+
+		// PNGs have straight alpha
+		let resources = Resources::new(
+			&device,
+			ColourUniform::new(config, true, false),
+			config.spectrogram_length,
+		);
+
+		let specializations = ShaderSpecializations::new(config, false);
+		let (pipeline, _) = initial_pipeline(&device, FORMAT, shaders, specializations);
+
+		let image = AttachmentImage::with_usage(
+			&device.memory_allocator,
+			extent,
+			FORMAT,
+			ImageUsage {
+				color_attachment: true,
+				transfer_src: true,
+				..ImageUsage::empty()
+			},
+		).unwrap();
+
+		let buffer = CpuAccessibleBuffer::from_iter(
+			&device.memory_allocator,
+			BufferUsage {
+				transfer_dst: true,
+				..BufferUsage::empty()
+			},
+			true,
+			(0..(extent[0] * extent[1] * 4)).map(|_| 0u8),
+		).unwrap();
+
+		let viewport = Viewport {
+			origin: [0.0, 0.0],
+			dimensions: [extent[0] as f32, extent[1] as f32],
+			depth_range: 0.0..1.0,
+		};
+
+		let mut builder = AutoCommandBufferBuilder::primary(
+			&device.command_buffer_allocator,
+			device.queue_family_index,
+			CommandBufferUsage::MultipleSubmit,
+		).unwrap();
+
+		pipeline.record_draw(
+			&mut builder,
+			&device,
+			ImageView::new_default(image.clone()).unwrap(),
+			&resources,
+			vertices,
+			viewport,
+		);
+
+		builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone())).unwrap();
+
+		let command_buffer = builder.build().map(Arc::new).unwrap();
+
+		Ok(Self {
+			device,
+			resources,
+			buffer,
+			command_buffer,
+			extent,
+		})
+	}
+
+	/// Draws `frame` and waits for the result.
+	pub fn render(&mut self, frame: &Frame) -> Result<Snapshot, RenderError> {
+		self.resources.write_colours();
+		self.resources.write(frame, self.extent).map_err(RenderError::Write)?;
+
+		let mut future = sync::now((&self.device).into()).boxed();
+
+		if let Some(uploads) = self.resources.frame_uploads(&self.device) {
+			future = future.then_execute(Arc::clone(&self.device.queue), uploads)
+				.map_err(RenderError::Execute)?
+				.boxed();
+		}
+
+		future
+			.then_execute(Arc::clone(&self.device.queue), Arc::clone(&self.command_buffer))
+			.map_err(RenderError::Execute)?
+			.then_signal_fence_and_flush()
+			.map_err(RenderError::Flush)?
+			.wait(None)
+			.map_err(RenderError::Flush)?;
+
+		let pixels = self.buffer.read().map_err(RenderError::Read)?.to_vec();
+
+		Ok(Snapshot { extent: self.extent, pixels })
+	}
+}
+
+impl Snapshot {
+	pub fn save_png(&self, path: &Path) -> Result<(), png::EncodingError> {
+		let file = BufWriter::new(File::create(path)?);
+
+		let [width, height] = self.extent;
+		let mut encoder = png::Encoder::new(file, width, height);
+		encoder.set_color(png::ColorType::Rgba);
+		encoder.set_depth(png::BitDepth::Eight);
+		encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+		let mut writer = encoder.write_header()?;
+		writer.write_image_data(&self.pixels)?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use crate::graphics::ColourScheme;
+	use crate::layout::{self, Shape};
+
+	use super::*;
+
+	/// Renders `frame` and compares it with `tests/golden/<name>.png`, allowing
+	/// for rounding in the colour conversions. With `UPDATE_GOLDEN` set the
+	/// render is written there instead.
+	///
+	/// Skipped when there is no Vulkan device to render with, except in CI
+	/// where one is installed so the comparison always runs.
+	fn assert_golden(name: &str, config: &Config, frame: &Frame) {
+		let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+			.join("tests/golden")
+			.join(name)
+			.with_extension("png");
+
+		let layout = layout::from_config(config, None).unwrap();
+		let extent = [config.width, config.height];
+
+		let mut offscreen = match Offscreen::new(config, &layout, extent, &ShaderCode::builtin()) {
+			Ok(offscreen) => offscreen,
+			Err(error) if std::env::var_os("CI").is_some() => panic!("Can't render {}: {}", name, error),
+			Err(error) => return eprintln!("Skipping {}: {}", name, error),
+		};
+
+		let snapshot = offscreen.render(frame).unwrap();
+
+		if std::env::var_os("UPDATE_GOLDEN").is_some() {
+			return snapshot.save_png(&path).unwrap();
+		}
+
+		let decoder = png::Decoder::new(File::open(&path).unwrap());
+		let mut reader = decoder.read_info().unwrap();
+		let mut golden = vec![0; reader.output_buffer_size()];
+		let info = reader.next_frame(&mut golden).unwrap();
+
+		assert_eq!([info.width, info.height], extent, "{} is the wrong size", path.display());
+
+		let mismatch = snapshot.pixels.chunks_exact(4)
+			.zip(golden.chunks_exact(4))
+			.position(|(rendered, expected)| {
+				rendered.iter().zip(expected).any(|(&rendered, &expected)| rendered.abs_diff(expected) > 2)
+			});
+
+		if let Some(index) = mismatch {
+			let [x, y] = [index as u32 % extent[0], index as u32 / extent[0]];
+			panic!("{} differs from {} at ({}, {})", name, path.display(), x, y);
+		}
+	}
+
+	#[test]
+	fn bars_solid() {
+		let config = Config {
+			shape: Shape::Bars,
+			bar_count: 4,
+			bar_gap: 0.5,
+			colour_scheme: ColourScheme::Solid,
+			colours: vec!["#ff8000".parse().unwrap()],
+			background: "#204060".parse().unwrap(),
+			width: 64,
+			height: 32,
+			..Config::default()
+		};
+
+		// the bar tops fall halfway between two rows of pixels, as do their
+		// sides between columns, so no pixel is on an edge
+		let mut frame = Frame::empty();
+		frame.spectrum.fill(0.5);
+
+		assert_golden("bars-solid", &config, &frame);
+	}
+}
@@ -1,16 +1,25 @@
+use std::fmt;
 use std::sync::Arc;
 
-use vulkano::{command_buffer::PrimaryAutoCommandBuffer};
-use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderingAttachmentInfo, RenderingInfo};
+use vulkano::descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType};
 use vulkano::format::Format;
-use vulkano::device::Device;
-use vulkano::shader::ShaderModule;
-use vulkano::pipeline::{GraphicsPipeline, Pipeline as VkPipeline, PipelineLayout};
-use vulkano::shader::spirv::ExecutionModel;
-use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::device::Device as VkDevice;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::shader::{ShaderCreationError, ShaderModule, ShaderStages};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline as VkPipeline, PipelineBindPoint, PipelineLayout};
+use vulkano::pipeline::graphics::GraphicsPipelineCreationError;
+use vulkano::pipeline::layout::PipelineLayoutCreateInfo;
+use vulkano::shader::reflect;
+use vulkano::shader::spirv::{ExecutionModel, Spirv, SpirvError};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::pipeline::graphics::render_pass::PipelineRenderingCreateInfo;
+use vulkano::render_pass::{LoadOp, StoreOp};
 
-use super::vertex::Vertex;
+use super::device::Device;
+use super::resources::Resources;
+use super::shaders::{ShaderCode, SPIRV_MAGIC};
+use super::vertex::{Vertex, VisualiserVertexVec};
 use super::{vertex::VisualiserVertex, ShaderSpecializations};
 
 pub(crate) struct Pipeline {
@@ -24,40 +33,179 @@ impl From<&Pipeline> for Arc<GraphicsPipeline> {
 	}
 }
 
+#[derive(Debug)]
+pub(crate) enum PipelineError {
+	/// the stage doesn't start with the SPIR-V magic number
+	NotSpirv(&'static str),
+	/// the stage couldn't be parsed to find its entry points and resources
+	Spirv(&'static str, SpirvError),
+	Module(ShaderCreationError),
+	/// the stage has no `main` function
+	MissingEntryPoint(&'static str),
+	/// the shaders do not fit the vertices, resources or each other
+	Build(GraphicsPipelineCreationError),
+}
+
+impl fmt::Display for PipelineError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PipelineError::NotSpirv(stage) => write!(f, "{} shader is not a SPIR-V module", stage),
+			PipelineError::Spirv(stage, error) => write!(f, "invalid {} shader: {}", stage, error),
+			PipelineError::Module(error) => write!(f, "invalid shader module: {}", error),
+			PipelineError::MissingEntryPoint(stage) => write!(f, "{} shader has no main function", stage),
+			PipelineError::Build(error) => write!(f, "{}", error),
+		}
+	}
+}
+
 impl Pipeline {
 	pub fn layout(&self) -> Arc<PipelineLayout> {
 		Arc::clone(&self.pipeline.layout())
 	}
 
-	pub fn new(device: Arc<Device>, format: Format) -> Self {
-		let vs = unsafe {
-			ShaderModule::from_bytes(
-				Arc::clone(&device),
-				include_bytes!("../shaders/vert.spv")
-			).unwrap()
-		};
+	pub fn new(
+		device: Arc<VkDevice>,
+		format: Format,
+		shaders: &ShaderCode,
+		specializations: ShaderSpecializations,
+	) -> Result<Self, PipelineError> {
+		let vs = Self::module(Arc::clone(&device), &shaders.vertex, "vertex", ExecutionModel::Vertex)?;
+		let fs = Self::module(Arc::clone(&device), &shaders.fragment, "fragment", ExecutionModel::Fragment)?;
 
-		let fs = unsafe {
-			ShaderModule::from_bytes(
-				Arc::clone(&device),
-				include_bytes!("../shaders/frag.spv")
-			).unwrap()
-		};
+		let vertex = vs.entry_point_with_execution("main", ExecutionModel::Vertex)
+			.ok_or(PipelineError::MissingEntryPoint("vertex"))?;
+		let fragment = fs.entry_point_with_execution("main", ExecutionModel::Fragment)
+			.ok_or(PipelineError::MissingEntryPoint("fragment"))?;
 
-		let vertex = vs.entry_point_with_execution("main", ExecutionModel::Vertex).unwrap();
-		let fragment = fs.entry_point_with_execution("main", ExecutionModel::Fragment).unwrap();
+		let layout = Self::resource_layout(Arc::clone(&device));
 
 		let pipeline = GraphicsPipeline::start()
-			.vertex_shader(vertex, ShaderSpecializations {})
-			.fragment_shader(fragment, ShaderSpecializations {})
+			.vertex_shader(vertex, specializations)
+			.fragment_shader(fragment, specializations)
 			.render_pass(PipelineRenderingCreateInfo {
 				color_attachment_formats: vec![Some(format)],
 				..Default::default()
 			})
 			.vertex_input_state(VisualiserVertex::input_state())
 			.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-			.build(device).unwrap();
+			.with_pipeline_layout(device, layout)
+			.map_err(PipelineError::Build)?;
+
+		Ok(Self { pipeline, format })
+	}
+
+	/// Checks everything about `words` which can be checked before it is given
+	/// to the driver: that it is SPIR-V, that it parses, and that reflection
+	/// finds a `main` entry point for the stage.
+	///
+	/// Building the pipeline then checks the entry point's interface and
+	/// resources against the vertices and the resource layout.
+	fn module(
+		device: Arc<VkDevice>,
+		words: &[u32],
+		stage: &'static str,
+		execution_model: ExecutionModel,
+	) -> Result<Arc<ShaderModule>, PipelineError> {
+		if words.first() != Some(&SPIRV_MAGIC) {
+			return Err(PipelineError::NotSpirv(stage));
+		}
+
+		let spirv = Spirv::new(words).map_err(|error| PipelineError::Spirv(stage, error))?;
+
+		let has_main = reflect::entry_points(&spirv)
+			.any(|(name, model, _)| name == "main" && model == execution_model);
+
+		if !has_main {
+			return Err(PipelineError::MissingEntryPoint(stage));
+		}
 
-		Self { pipeline, format }
+		// vulkano doesn't validate the code beyond the reflection above, so
+		// this is as far as broken SPIR-V can be kept from the driver
+		unsafe {
+			ShaderModule::from_words_with_data(
+				device,
+				words,
+				spirv.version(),
+				reflect::spirv_capabilities(&spirv),
+				reflect::spirv_extensions(&spirv),
+				reflect::entry_points(&spirv),
+			)
+		}.map_err(PipelineError::Module)
 	}
-}
\ No newline at end of file
+
+	/// Records uploading the resources and drawing `vertices` to `attachment`,
+	/// whether that is a swapchain image or an offscreen one.
+	pub fn record_draw(
+		&self,
+		builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+		device: &Device,
+		attachment: Arc<dyn ImageViewAbstract>,
+		resources: &Resources,
+		vertices: &VisualiserVertexVec,
+		viewport: Viewport,
+	) {
+		resources.record_uploads(builder);
+
+		builder
+		.begin_rendering(RenderingInfo {
+			color_attachments: vec![Some(RenderingAttachmentInfo {
+				load_op: LoadOp::Clear,
+				store_op: StoreOp::Store,
+				clear_value: Some(resources.background().into()),
+				..RenderingAttachmentInfo::image_view(attachment)
+			})],
+			..Default::default()
+		}).unwrap()
+		.set_viewport(0, [viewport])
+		.bind_pipeline_graphics(self.into())
+		.bind_vertex_buffers(0, vertices.position_buffer(&device.memory_allocator))
+		.bind_vertex_buffers(1, vertices.frequency_buffer(&device.memory_allocator))
+		.bind_vertex_buffers(2, vertices.amplitude_buffer(&device.memory_allocator))
+		.bind_descriptor_sets(
+			PipelineBindPoint::Graphics,
+			self.layout(),
+			0,
+			vec![resources.descriptor_set(device, self.into())],
+		)
+		.draw(vertices.len() as u32, 1, 0, 0).unwrap()
+		.end_rendering().unwrap();
+	}
+
+	/// Every resource in `Resources`, whether or not the shaders use it, so
+	/// custom shaders are checked against it and share its descriptor set.
+	fn resource_layout(device: Arc<VkDevice>) -> Arc<PipelineLayout> {
+		let stages = ShaderStages {
+			vertex: true,
+			fragment: true,
+			..ShaderStages::empty()
+		};
+
+		let binding = |descriptor_type| DescriptorSetLayoutBinding {
+			stages,
+			..DescriptorSetLayoutBinding::descriptor_type(descriptor_type)
+		};
+
+		let set_layout = DescriptorSetLayout::new(
+			Arc::clone(&device),
+			DescriptorSetLayoutCreateInfo {
+				bindings: [
+					(0, binding(DescriptorType::CombinedImageSampler)),
+					(1, binding(DescriptorType::CombinedImageSampler)),
+					(2, binding(DescriptorType::UniformBuffer)),
+					(3, binding(DescriptorType::UniformBuffer)),
+					(4, binding(DescriptorType::CombinedImageSampler)),
+					(5, binding(DescriptorType::CombinedImageSampler)),
+				].into(),
+				..Default::default()
+			},
+		).unwrap();
+
+		PipelineLayout::new(
+			device,
+			PipelineLayoutCreateInfo {
+				set_layouts: vec![set_layout],
+				..Default::default()
+			},
+		).unwrap()
+	}
+}
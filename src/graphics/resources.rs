@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::cpu_access::WriteLockError;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline};
+
+use crate::visualiser::Frame;
+
+use super::colours::ColourUniform;
+use super::device::Device;
+use super::sampler::{Components, Sampler};
+use super::spectrogram::Spectrogram;
+
+/// The `FrameInfo` uniform block available to the shaders, laid out for
+/// std140.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct FrameUniform {
+	time: f32,
+	delta: f32,
+	resolution: [f32; 2],
+	rms: f32,
+	peak: f32,
+	bass: f32,
+	mid: f32,
+	treble: f32,
+	/// a GLSL `bool`, which is 4 bytes in a uniform block
+	beat: u32,
+	spectrogram_row: u32,
+	audio_state: u32,
+}
+
+impl FrameUniform {
+	fn new(frame: &Frame, resolution: [u32; 2], spectrogram_row: u32) -> Self {
+		Self {
+			time: frame.time,
+			delta: frame.delta,
+			resolution: resolution.map(|length| length as f32),
+			rms: frame.levels.rms,
+			peak: frame.levels.peak,
+			bass: frame.levels.bass,
+			mid: frame.levels.mid,
+			treble: frame.levels.treble,
+			beat: frame.levels.beat as u32,
+			spectrogram_row,
+			audio_state: frame.source_state as u32,
+		}
+	}
+}
+
+/// Everything bound to the descriptor set used by the shaders.
+pub(crate) struct Resources {
+	/// binding 0: `frequency_magnitude`
+	spectrum: Sampler,
+	/// binding 1: `peak_magnitude`
+	peaks: Sampler,
+	/// binding 2: `Colours`
+	colours: Arc<CpuAccessibleBuffer<ColourUniform>>,
+	/// binding 3: `FrameInfo`
+	frame: Arc<CpuAccessibleBuffer<FrameUniform>>,
+	/// binding 4: `waveform`
+	waveform: Sampler,
+	/// binding 5: `spectrogram`
+	spectrogram: Spectrogram,
+	/// colours waiting for the GPU to stop using the buffer
+	pending_colours: Option<ColourUniform>,
+	/// the background the surface is cleared to
+	background: [f32; 4],
+}
+
+impl Resources {
+	pub fn new(device: &Device, colours: ColourUniform, spectrogram_length: u32) -> Self {
+		let background = colours.background();
+
+		let uniform_usage = BufferUsage {
+			uniform_buffer: true,
+			..BufferUsage::empty()
+		};
+
+		let colours = CpuAccessibleBuffer::from_data(
+			&device.memory_allocator,
+			uniform_usage,
+			false,
+			colours,
+		).unwrap();
+
+		let frame = CpuAccessibleBuffer::from_data(
+			&device.memory_allocator,
+			uniform_usage,
+			true,
+			FrameUniform::default(),
+		).unwrap();
+
+		Self {
+			spectrum: Sampler::new(device, Components::One),
+			peaks: Sampler::new(device, Components::One),
+			colours,
+			frame,
+			waveform: Sampler::new(device, Components::Two),
+			spectrogram: Spectrogram::new(device, spectrogram_length),
+			pending_colours: None,
+			background,
+		}
+	}
+
+	pub fn background(&self) -> [f32; 4] {
+		self.background
+	}
+
+	/// The colours are written by the next `write_colours` which finds the
+	/// buffer unused.
+	pub fn set_colours(&mut self, colours: ColourUniform) {
+		self.background = colours.background();
+		self.pending_colours = Some(colours);
+	}
+
+	pub fn write_colours(&mut self) {
+		if let Some(colours) = self.pending_colours {
+			if let Ok(mut buffer) = self.colours.write() {
+				*buffer = colours;
+				self.pending_colours = None;
+			}
+		}
+	}
+
+	pub fn spectrogram_length(&self) -> u32 {
+		self.spectrogram.length()
+	}
+
+	/// Replaces the spectrogram with an empty one holding `length` rows.
+	/// The command buffers have to be recorded again to use it.
+	pub fn set_spectrogram_length(&mut self, device: &Device, length: u32) {
+		self.spectrogram = Spectrogram::new(device, length);
+	}
+
+	/// Fills the staging buffers which are copied to the GPU at the start of
+	/// every command buffer, along with the per frame uniforms.
+	pub fn write(&mut self, frame: &Frame, resolution: [u32; 2]) -> Result<(), WriteLockError> {
+		let mut spectrum = self.spectrum.buffer.write()?;
+		let mut peaks = self.peaks.buffer.write()?;
+		let mut uniform = self.frame.write()?;
+		let mut waveform = self.waveform.buffer.write()?;
+
+		self.spectrogram.write(&frame.spectrum, frame.step)?;
+
+		spectrum.copy_from_slice(frame.spectrum.as_slice());
+		peaks.copy_from_slice(frame.peaks.as_slice());
+
+		for (texel, samples) in waveform.chunks_exact_mut(2).zip(frame.waveform.iter()) {
+			texel.copy_from_slice(samples);
+		}
+		*uniform = FrameUniform::new(frame, resolution, self.spectrogram.row());
+
+		Ok(())
+	}
+
+	pub fn record_uploads(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+		builder
+			.copy_buffer_to_image(self.spectrum.copy_operation()).unwrap()
+			.copy_buffer_to_image(self.peaks.copy_operation()).unwrap()
+			.copy_buffer_to_image(self.waveform.copy_operation()).unwrap();
+	}
+
+	/// Uploads which only happen on some frames, so are submitted separately
+	/// before the pre-recorded command buffer.
+	pub fn frame_uploads(&mut self, device: &Device) -> Option<PrimaryAutoCommandBuffer<StandardCommandBufferAlloc>> {
+		self.spectrogram.upload(device)
+	}
+
+	pub fn descriptor_set(&self, device: &Device, pipeline: Arc<GraphicsPipeline>) -> Arc<PersistentDescriptorSet> {
+		PersistentDescriptorSet::new(
+			&device.descriptor_allocator,
+			Arc::clone(pipeline.layout().set_layouts().first().unwrap()),
+			[
+				self.spectrum.write_descriptor(0),
+				self.peaks.write_descriptor(1),
+				WriteDescriptorSet::buffer(2, Arc::clone(&self.colours)),
+				WriteDescriptorSet::buffer(3, Arc::clone(&self.frame)),
+				self.waveform.write_descriptor(4),
+				self.spectrogram.write_descriptor(5),
+			],
+		).unwrap()
+	}
+}
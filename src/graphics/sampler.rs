@@ -1,11 +1,34 @@
 use std::sync::Arc;
 
-use vulkano::{image::{StorageImage, ImageDimensions, view::ImageView}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, sampler::{Sampler as VkSampler, SamplerCreateInfo, SamplerAddressMode, Filter}, format::Format, buffer::{BufferUsage, CpuAccessibleBuffer}, command_buffer::CopyBufferToImageInfo, pipeline::{GraphicsPipeline, Pipeline}};
+use vulkano::{image::{StorageImage, ImageDimensions, view::ImageView}, descriptor_set::WriteDescriptorSet, sampler::{Sampler as VkSampler, SamplerCreateInfo, SamplerAddressMode, Filter}, format::Format, buffer::{BufferUsage, CpuAccessibleBuffer}, command_buffer::CopyBufferToImageInfo};
 
 use crate::BUFFER_SIZE;
 
 use super::device::Device;
 
+/// How many values each texel holds.
+#[derive(Clone, Copy)]
+pub(super) enum Components {
+	One,
+	Two,
+}
+
+impl Components {
+	fn format(self) -> Format {
+		match self {
+			Components::One => Format::R32_SFLOAT,
+			Components::Two => Format::R32G32_SFLOAT,
+		}
+	}
+
+	fn count(self) -> usize {
+		match self {
+			Components::One => 1,
+			Components::Two => 2,
+		}
+	}
+}
+
 pub(crate) struct Sampler {
 	sampler: Arc<VkSampler>,
 	pub buffer: Arc<CpuAccessibleBuffer<[f32]>>,
@@ -13,7 +36,10 @@ pub(crate) struct Sampler {
 }
 
 impl Sampler {
-	pub fn new(device: &Device) -> Self {
+	/// A texture of `BUFFER_SIZE` texels with `components` each.
+	/// The buffer holds the components of each texel side by side.
+	pub fn new(device: &Device, components: Components) -> Self {
+
 		let buffer = CpuAccessibleBuffer::from_iter(
 			&device.memory_allocator,
 			BufferUsage {
@@ -21,7 +47,7 @@ impl Sampler {
 				..BufferUsage::empty()
 			},
 			true,
-			[f32::default(); BUFFER_SIZE].into_iter(),
+			std::iter::repeat(f32::default()).take(BUFFER_SIZE * components.count()),
 		).unwrap();
 		
 		let image = StorageImage::new(
@@ -30,7 +56,7 @@ impl Sampler {
 				width: BUFFER_SIZE as u32,
 				array_layers: 1,
 			},
-			Format::R32_SFLOAT,
+			components.format(),
 			[device.queue_family_index],
 		).unwrap();
 
@@ -50,12 +76,8 @@ impl Sampler {
 		Self { sampler, buffer, image_view }
 	}
 
-	pub fn descriptor_set(&self, device: &Device, pipeline: Arc<GraphicsPipeline>) -> Arc<PersistentDescriptorSet> {
-		PersistentDescriptorSet::new(
-			&device.descriptor_allocator,
-			Arc::clone(pipeline.layout().set_layouts().first().unwrap()),
-			[WriteDescriptorSet::image_view_sampler(0, self.image_view.clone(), Arc::clone(&self.sampler))],
-		).unwrap()
+	pub fn write_descriptor(&self, binding: u32) -> WriteDescriptorSet {
+		WriteDescriptorSet::image_view_sampler(binding, self.image_view.clone(), Arc::clone(&self.sampler))
 	}
 
 	pub fn copy_operation(&self) -> CopyBufferToImageInfo {
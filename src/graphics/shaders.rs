@@ -0,0 +1,145 @@
+use std::{fmt, io, path::{Path, PathBuf}, sync::Arc};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use shaderc::{Compiler, ShaderKind};
+
+use crate::config::Config;
+
+/// The first word of every SPIR-V module.
+pub(super) const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// SPIR-V for each stage of the pipeline.
+#[derive(Clone)]
+pub(crate) struct ShaderCode {
+	pub vertex: Arc<[u32]>,
+	pub fragment: Arc<[u32]>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ShaderError {
+	Io(PathBuf, io::Error),
+	/// shaderc couldn't be initialised to compile GLSL
+	Compiler,
+	Compile(PathBuf, shaderc::Error),
+	InvalidSpirv(PathBuf),
+}
+
+impl fmt::Display for ShaderError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ShaderError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+			ShaderError::Compiler => write!(f, "failed to initialise the shader compiler"),
+			ShaderError::Compile(path, error) => write!(f, "failed to compile {}:\n{}", path.display(), error),
+			ShaderError::InvalidSpirv(path) => write!(f, "{} is not a SPIR-V module", path.display()),
+		}
+	}
+}
+
+impl ShaderCode {
+	/// The shaders compiled into the program from `src/shaders`.
+	pub fn builtin() -> Self {
+		Self {
+			vertex: words(include_bytes!("../shaders/vert.spv")).unwrap(),
+			fragment: words(include_bytes!("../shaders/frag.spv")).unwrap(),
+		}
+	}
+
+	/// Loads the shaders given in `config`, using the built-in ones for any
+	/// stage which is not set.
+	pub fn load(config: &Config) -> Result<Self, ShaderError> {
+		let builtin = Self::builtin();
+
+		Ok(Self {
+			vertex: match &config.vertex_shader {
+				Some(path) => load(path, ShaderKind::Vertex)?,
+				None => builtin.vertex,
+			},
+			fragment: match &config.fragment_shader {
+				Some(path) => load(path, ShaderKind::Fragment)?,
+				None => builtin.fragment,
+			},
+		})
+	}
+}
+
+/// Reads SPIR-V from files ending in `.spv`, otherwise compiles the file as
+/// GLSL.
+fn load(path: &Path, kind: ShaderKind) -> Result<Arc<[u32]>, ShaderError> {
+	if path.extension().map_or(false, |extension| extension == "spv") {
+		let bytes = std::fs::read(path).map_err(|error| ShaderError::Io(path.to_owned(), error))?;
+
+		return words(&bytes).ok_or_else(|| ShaderError::InvalidSpirv(path.to_owned()));
+	}
+
+	let source = std::fs::read_to_string(path).map_err(|error| ShaderError::Io(path.to_owned(), error))?;
+
+	let compiler = Compiler::new().ok_or(ShaderError::Compiler)?;
+	let artifact = compiler.compile_into_spirv(&source, kind, &path.to_string_lossy(), "main", None)
+		.map_err(|error| ShaderError::Compile(path.to_owned(), error))?;
+
+	Ok(artifact.as_binary().into())
+}
+
+fn words(bytes: &[u8]) -> Option<Arc<[u32]>> {
+	if bytes.len() % 4 != 0 {
+		return None;
+	}
+
+	let words = bytes.chunks_exact(4)
+		.map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+		.collect::<Arc<[u32]>>();
+
+	(words.first() == Some(&SPIRV_MAGIC)).then_some(words)
+}
+
+/// Calls `on_change` whenever one of the shader files set in `config`
+/// changes.
+///
+/// Returns `None` if there are no shader files to watch.
+/// The watcher stops when it is dropped.
+pub fn watch(
+	config: &Config,
+	mut on_change: impl FnMut() + Send + 'static,
+) -> notify::Result<Option<RecommendedWatcher>> {
+	let paths = [&config.vertex_shader, &config.fragment_shader]
+		.into_iter()
+		.flatten()
+		.cloned()
+		.collect::<Vec<_>>();
+
+	if paths.is_empty() {
+		return Ok(None);
+	}
+
+	// as with the config, watch the directories in case a file is replaced
+	let mut directories = paths.iter()
+		.map(|path| match path.parent() {
+			Some(directory) if directory != Path::new("") => directory.to_owned(),
+			_ => PathBuf::from("."),
+		})
+		.collect::<Vec<_>>();
+
+	directories.dedup();
+
+	let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+		let event = match event {
+			Ok(event) => event,
+			Err(error) => return eprintln!("Error watching shaders: {}", error),
+		};
+
+		let affects_shaders = !matches!(event.kind, EventKind::Access(_))
+			&& event.paths.iter().any(|changed| {
+				paths.iter().any(|path| changed.file_name() == path.file_name())
+			});
+
+		if affects_shaders {
+			on_change();
+		}
+	})?;
+
+	for directory in directories {
+		watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+	}
+
+	Ok(Some(watcher))
+}
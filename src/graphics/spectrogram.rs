@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::cpu_access::WriteLockError;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, BufferImageCopy, ClearColorImageInfo, CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
+use vulkano::descriptor_set::WriteDescriptorSet;
+use vulkano::format::Format;
+use vulkano::image::{ImageAccess, ImageDimensions, StorageImage, view::ImageView};
+use vulkano::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+
+use crate::BUFFER_SIZE;
+
+use super::device::Device;
+
+/// Direction the spectrogram moves in, the value is the `SCROLL` constant in
+/// the fragment shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Scroll {
+	/// Newest at the bottom, frequency across
+	Up = 0,
+	/// Newest at the top, frequency across
+	Down = 1,
+	/// Newest on the right, frequency upwards
+	Left = 2,
+	/// Newest on the left, frequency upwards
+	Right = 3,
+}
+
+/// The most recent spectra as the rows of an image, used as a ring buffer so
+/// only the newest row is uploaded each frame.
+///
+/// There is a row for every analysis step rather than every draw, so views
+/// drawn at different rates scroll at the same speed.
+pub(crate) struct Spectrogram {
+	sampler: Arc<Sampler>,
+	/// staging buffer for the newest row
+	buffer: Arc<CpuAccessibleBuffer<[f32]>>,
+	image_view: Arc<ImageView<StorageImage>>,
+	/// analysis step of the newest row
+	step: Option<u64>,
+	/// rows up to and including the newest which have not been uploaded
+	pending: u32,
+	/// the image starts with undefined contents, so is cleared with the
+	/// first upload
+	cleared: bool,
+}
+
+impl Spectrogram {
+	pub fn new(device: &Device, length: u32) -> Self {
+		let buffer = CpuAccessibleBuffer::from_iter(
+			&device.memory_allocator,
+			BufferUsage {
+				transfer_src: true,
+				..BufferUsage::empty()
+			},
+			true,
+			[f32::default(); BUFFER_SIZE].into_iter(),
+		).unwrap();
+
+		let image = StorageImage::new(
+			&device.memory_allocator,
+			ImageDimensions::Dim2d {
+				width: BUFFER_SIZE as u32,
+				height: length.max(1),
+				array_layers: 1,
+			},
+			Format::R32_SFLOAT,
+			[device.queue_family_index],
+		).unwrap();
+
+		let image_view = ImageView::new_default(image).unwrap();
+
+		// rows are read with texelFetch, but a combined image sampler still
+		// needs a sampler
+		let sampler = Sampler::new(
+			device.into(),
+			SamplerCreateInfo {
+				mag_filter: Filter::Nearest,
+				min_filter: Filter::Nearest,
+				address_mode: [SamplerAddressMode::ClampToEdge; 3],
+				..Default::default()
+			}
+		).unwrap();
+
+		Self {
+			sampler,
+			buffer,
+			image_view,
+			step: None,
+			pending: 0,
+			cleared: false,
+		}
+	}
+
+	pub fn length(&self) -> u32 {
+		self.image_view.image().dimensions().height()
+	}
+
+	/// Row holding the newest spectrum.
+	pub fn row(&self) -> u32 {
+		self.step.map_or(0, |step| (step % self.length() as u64) as u32)
+	}
+
+	/// Stages `spectrum` as the row for analysis `step`, replacing the oldest.
+	/// Steps this view wasn't drawn for are filled with the same spectrum,
+	/// and a step which already has its row is left alone.
+	pub fn write(&mut self, spectrum: &[f32; BUFFER_SIZE], step: u64) -> Result<(), WriteLockError> {
+		let new_rows = match self.step {
+			Some(written) if step <= written => return Ok(()),
+			Some(written) => step - written,
+			None => 1,
+		};
+
+		self.buffer.write()?.copy_from_slice(spectrum);
+
+		self.step = Some(step);
+		self.pending = (self.pending as u64 + new_rows).min(self.length() as u64) as u32;
+
+		Ok(())
+	}
+
+	/// A command buffer copying the newest row into the image, and into any
+	/// rows skipped since the last upload.
+	pub fn upload(
+		&mut self,
+		device: &Device,
+	) -> Option<PrimaryAutoCommandBuffer<StandardCommandBufferAlloc>> {
+		if self.pending == 0 {
+			return None;
+		}
+
+		let mut builder = AutoCommandBufferBuilder::primary(
+			&device.command_buffer_allocator,
+			device.queue_family_index,
+			CommandBufferUsage::OneTimeSubmit,
+		).unwrap();
+
+		let image = self.image_view.image().clone();
+
+		if !self.cleared {
+			builder.clear_color_image(ClearColorImageInfo::image(image.clone())).unwrap();
+		}
+
+		let length = self.length();
+		let newest = self.row();
+
+		let regions = (0..self.pending)
+			.map(|age| BufferImageCopy {
+				image_subresource: image.subresource_layers(),
+				image_offset: [0, (newest + length - age) % length, 0],
+				image_extent: [BUFFER_SIZE as u32, 1, 1],
+				..Default::default()
+			})
+			.collect();
+
+		builder.copy_buffer_to_image(CopyBufferToImageInfo {
+			regions,
+			..CopyBufferToImageInfo::buffer_image(self.buffer.clone(), image)
+		}).unwrap();
+
+		self.pending = 0;
+		self.cleared = true;
+
+		Some(builder.build().unwrap())
+	}
+
+	pub fn write_descriptor(&self, binding: u32) -> WriteDescriptorSet {
+		WriteDescriptorSet::image_view_sampler(binding, self.image_view.clone(), Arc::clone(&self.sampler))
+	}
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use vulkano::instance::Instance;
@@ -24,12 +26,30 @@ impl From<&Surface> for Arc<VkSurface> {
     }
 }
 
+#[derive(Debug)]
+pub(crate) enum SurfaceError {
+	/// the device shared with the other surfaces can't present to this one,
+	/// e.g. an output connected to a different GPU
+	Unsupported,
+}
+
+impl fmt::Display for SurfaceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SurfaceError::Unsupported => write!(f, "the graphics device can't present to it"),
+		}
+	}
+}
+
 impl Surface {
+	/// Uses `device` if there is one, otherwise creates a device which can
+	/// present to the surface.
 	pub fn from_wayland(
 		instance: Arc<Instance>,
 		display: &WlDisplay,
 		surface: &WlSurface,
-	) -> (Self, Device) {
+		device: Option<Rc<Device>>,
+	) -> Result<(Self, Rc<Device>), SurfaceError> {
 		let display_pointer = display.id().as_ptr();
 		let surface_pointer = surface.id().as_ptr();
 
@@ -42,7 +62,15 @@ impl Surface {
 			)
 		}.expect("Failed to create vulkan surface");
 
-		let device = Device::new(&instance, &surface);
+		let device = device.unwrap_or_else(|| Rc::new(Device::new(&instance, Some(&surface))));
+
+		let supported = device.physical_device()
+			.surface_support(device.queue_family_index, &surface)
+			.unwrap_or(false);
+
+		if !supported {
+			return Err(SurfaceError::Unsupported);
+		}
 
 		let capabilities = device.physical_device()
 			.surface_capabilities(&surface, Default::default())
@@ -83,6 +111,6 @@ impl Surface {
 			framebuffer_count,
 		};
 
-		(surface, device)
+		Ok((surface, device))
 	}
 }
\ No newline at end of file
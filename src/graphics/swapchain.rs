@@ -4,16 +4,14 @@ use vulkano::command_buffer::*;
 use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
 use vulkano::image::{ImageUsage, ImageAccess};
 use vulkano::image::{view::ImageView, SwapchainImage};
-use vulkano::pipeline::PipelineBindPoint;
 use vulkano::pipeline::graphics::viewport::Viewport;
-use vulkano::render_pass::{LoadOp, StoreOp};
-use vulkano::swapchain::{Swapchain as VkSwapchain, SwapchainCreateInfo, SwapchainPresentInfo, SwapchainAcquireFuture};
+use vulkano::swapchain::{Swapchain as VkSwapchain, SwapchainCreateInfo, SwapchainPresentInfo, SwapchainAcquireFuture, AcquireError, SwapchainCreationError};
 
 use super::pipeline::Pipeline;
 use super::surface::Surface;
 use super::device::Device;
 use super::vertex::VisualiserVertexVec;
-use super::sampler::Sampler;
+use super::resources::Resources;
 
 struct Framebuffer {
 	attachment_image: Arc<ImageView<SwapchainImage>>,
@@ -25,7 +23,7 @@ impl Framebuffer {
 		image: Arc<SwapchainImage>,
 		device: &Device,
 		pipeline: &Pipeline,
-		sampler: &Sampler,
+		resources: &Resources,
 		vertices: &VisualiserVertexVec,
 		viewport: Viewport,
 	) -> Self {
@@ -35,7 +33,7 @@ impl Framebuffer {
 			pipeline,
 			device,
 			&attachment_image,
-			sampler,
+			resources,
 			vertices,
 			viewport
 		);
@@ -47,7 +45,7 @@ impl Framebuffer {
 		pipeline: &Pipeline,
 		device: &Device,
 		attachment_image: &Arc<ImageView<SwapchainImage>>,
-		sampler: &Sampler,
+		resources: &Resources,
 		vertices: &VisualiserVertexVec,
 		viewport: Viewport,
 	) -> Arc<PrimaryAutoCommandBuffer<StandardCommandBufferAlloc>> {
@@ -57,31 +55,15 @@ impl Framebuffer {
 			CommandBufferUsage::MultipleSubmit,
 		).unwrap();
 
-		builder
-		.copy_buffer_to_image(sampler.copy_operation()).unwrap()
-		.begin_rendering(RenderingInfo {
-			color_attachments: vec![Some(RenderingAttachmentInfo {
-				load_op: LoadOp::Clear,
-				store_op: StoreOp::Store,
-				clear_value: Some([0.0; 4].into()),
-				..RenderingAttachmentInfo::image_view(attachment_image.clone())
-			})],
-			..Default::default()
-		}).unwrap()
-		.set_viewport(0, [viewport])
-		.bind_pipeline_graphics(pipeline.into())
-		.bind_vertex_buffers(0, vertices.position_buffer(&device.memory_allocator))
-		.bind_vertex_buffers(1, vertices.frequency_buffer(&device.memory_allocator))
-		.bind_vertex_buffers(2, vertices.amplitude_buffer(&device.memory_allocator))
-		.bind_descriptor_sets(
-			PipelineBindPoint::Graphics,
-			pipeline.layout(),
-			0,
-			vec![sampler.descriptor_set(device, pipeline.into())],
-		)
-		.draw(vertices.len() as u32, 1, 0, 0).unwrap()
-		.end_rendering().unwrap();
-		
+		pipeline.record_draw(
+			&mut builder,
+			device,
+			attachment_image.clone(),
+			resources,
+			vertices,
+			viewport,
+		);
+
 		builder.build().map(Arc::new).unwrap()
 	}
 }
@@ -94,24 +76,119 @@ pub(crate) struct Swapchain {
 }
 
 impl Swapchain {
-	pub fn next(&self) -> (SwapchainAcquireFuture, SwapchainPresentInfo, Arc<PrimaryAutoCommandBuffer>) {
+	/// The last value is true if the swapchain is suboptimal and should be
+	/// recreated, though it can still be presented to.
+	pub fn next(&self) -> Result<(
+		SwapchainAcquireFuture,
+		SwapchainPresentInfo,
+		Arc<PrimaryAutoCommandBuffer>,
+		bool,
+	), AcquireError> {
 		let (index, suboptimal, acquire_future) = vulkano::swapchain::acquire_next_image(
 			Arc::clone(&self.swapchain),
 			None,
-		).unwrap(); // TODO: handle AcquireError::OutOfDate
+		)?;
 
 		let present_info = SwapchainPresentInfo::swapchain_image_index(Arc::clone(&self.swapchain), index);
 		let command_buffer = Arc::clone(&self.framebuffers[index as usize].command_buffer);
 
-		(acquire_future, present_info, command_buffer)
+		Ok((acquire_future, present_info, command_buffer, suboptimal))
+	}
+
+	/// Re-records the command buffers after the vertices or resources they
+	/// use have changed.
+	pub fn record(&mut self, device: &Device, resources: &Resources, vertices: &VisualiserVertexVec) {
+		for framebuffer in self.framebuffers.iter_mut() {
+			framebuffer.command_buffer = Framebuffer::create_command_buffer(
+				&self.pipeline,
+				device,
+				&framebuffer.attachment_image,
+				resources,
+				vertices,
+				self.viewport.clone(),
+			);
+		}
+	}
+
+	pub fn set_pipeline(
+		&mut self,
+		device: &Device,
+		pipeline: Pipeline,
+		resources: &Resources,
+		vertices: &VisualiserVertexVec,
+	) {
+		self.pipeline = pipeline;
+		self.record(device, resources, vertices);
+	}
+
+	/// Rebuilds the swapchain images at `extent` along with everything drawn
+	/// to them.
+	pub fn resize(
+		&mut self,
+		device: &Device,
+		resources: &Resources,
+		vertices: &VisualiserVertexVec,
+		extent: [u32; 2],
+	) -> Result<(), SwapchainCreationError> {
+		let (swapchain, swapchain_images) = self.swapchain.recreate(SwapchainCreateInfo {
+			image_extent: extent,
+			..self.swapchain.create_info()
+		})?;
+
+		let (framebuffers, viewport) = Self::create_framebuffers(
+			swapchain_images,
+			device,
+			&self.pipeline,
+			resources,
+			vertices,
+		);
+
+		self.swapchain = swapchain;
+		self.framebuffers = framebuffers;
+		self.viewport = viewport;
+
+		Ok(())
+	}
+
+	fn create_framebuffers(
+		swapchain_images: Vec<Arc<SwapchainImage>>,
+		device: &Device,
+		pipeline: &Pipeline,
+		resources: &Resources,
+		vertices: &VisualiserVertexVec,
+	) -> (Box<[Framebuffer]>, Viewport) {
+		let dimensions = swapchain_images.first().unwrap()
+			.dimensions()
+			.width_height();
+
+		let viewport = Viewport {
+			origin: [0.0, 0.0],
+			dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+			depth_range: 0.0..1.0,
+		};
+
+		let framebuffers = swapchain_images.into_iter()
+			.map(|image| Framebuffer::create(
+				image,
+				device,
+				pipeline,
+				resources,
+				vertices,
+				viewport.clone()
+			))
+			.collect::<Vec<_>>()
+			.into_boxed_slice();
+
+		(framebuffers, viewport)
 	}
 
 	pub fn new(
 		device: &Device,
 		surface: &Surface,
-		sampler: &Sampler,
+		resources: &Resources,
 		vertices: &VisualiserVertexVec,
 		extent: [u32; 2],
+		pipeline: Pipeline,
 	) -> Self {
 		let (swapchain, swapchain_images) = VkSwapchain::new(
 			device.into(),
@@ -129,34 +206,15 @@ impl Swapchain {
 				..Default::default()
 			},
 		).expect("Failed to create swapchain");
-		
-		
-		let mut viewport = Viewport {
-			origin: [0.0, 0.0],
-			dimensions: [0.0, 0.0],
-			depth_range: 0.0..1.0,
-		};
-		
-		let dimensions = swapchain_images.first().unwrap()
-			.dimensions()
-			.width_height();
 
-		viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
+		let (framebuffers, viewport) = Self::create_framebuffers(
+			swapchain_images,
+			device,
+			&pipeline,
+			resources,
+			vertices,
+		);
 
-		let pipeline = Pipeline::new(device.into(), surface.format);
-	
-		let framebuffers = swapchain_images.into_iter()
-			.map(|image| Framebuffer::create(
-				image,
-				device,
-				&pipeline,
-				sampler,
-				vertices,
-				viewport.clone()
-			))
-			.collect::<Vec<_>>()
-			.into_boxed_slice();
-		
 		Self { swapchain, framebuffers, pipeline, viewport }
 	}
-}
\ No newline at end of file
+}
@@ -8,7 +8,8 @@ use vulkano::memory::allocator::MemoryAllocator;
 use vulkano::pipeline::graphics::vertex_input::*;
 use vulkano::format::Format;
 
-#[derive(StructOfArray)]
+#[derive(StructOfArray, Clone, Copy)]
+#[soa_derive(Clone)]
 pub(crate) struct VisualiserVertex {
 	pub position: [f32; 2],
 	pub frequency: f32,
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::audio::{AudioReader, AudioSink, SourceState};
+use crate::config::Config;
+use crate::graphics::{Offscreen, OffscreenError, RenderError, ShaderCode, VisualiserVertexVec};
+use crate::visualiser::{Analyser, BufferManager};
+
+/// How far the analysis steps between frames while waiting to take a
+/// snapshot, as though they were drawn at 60Hz.
+const FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+#[derive(Debug)]
+pub(crate) enum SnapshotError {
+	Audio(io::Error),
+	Device(OffscreenError),
+	Render(RenderError),
+	Png(png::EncodingError),
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SnapshotError::Audio(error) => write!(f, "failed to read the audio: {}", error),
+			SnapshotError::Device(error) => write!(f, "{}", error),
+			SnapshotError::Render(error) => write!(f, "{}", error),
+			SnapshotError::Png(error) => write!(f, "{}", error),
+		}
+	}
+}
+
+/// Analyses `delay` of the audio from `audio`, so the smoothing and gain have
+/// settled, then renders a single frame offscreen and saves it to `path` as a
+/// PNG.
+///
+/// The analysis steps through the audio a frame at a time as fast as it can
+/// be read rather than waiting for it, so the same audio and settings always
+/// give the same image.
+pub fn snapshot(
+	path: &Path,
+	config: &Config,
+	layout: &VisualiserVertexVec,
+	shaders: &ShaderCode,
+	audio: &mut dyn AudioReader,
+	delay: Duration,
+) -> Result<(), SnapshotError> {
+	let extent = [config.width, config.height];
+	let mut offscreen = Offscreen::new(config, layout, extent, shaders).map_err(SnapshotError::Device)?;
+
+	let visualiser = Arc::new(RwLock::new(BufferManager::new(config.clone())));
+	let sink = AudioSink::new(Arc::clone(&visualiser));
+	let mut analyser = Analyser::new(visualiser);
+
+	let frame_count = (delay.as_secs_f64() / FRAME_INTERVAL.as_secs_f64()).round() as u32;
+	let mut ended = false;
+	// seconds of audio due but not yet delivered
+	let mut owed = 0.0;
+	// of the latest block, until there is one a single frame is read to find it
+	let mut rate: Option<u32> = None;
+
+	for _ in 0..frame_count {
+		owed += FRAME_INTERVAL.as_secs_f64();
+
+		while !ended {
+			let due = rate.map_or(1, |rate| (owed * rate as f64).round() as usize);
+
+			if due == 0 {
+				break;
+			}
+
+			let Some((format, channels)) = audio.read(due).map_err(SnapshotError::Audio)? else {
+				ended = true;
+				sink.set_state(SourceState::Idle);
+				break;
+			};
+
+			let frames = channels.first().map_or(0, Vec::len);
+
+			if frames == 0 {
+				break;
+			}
+
+			let channels = channels.iter()
+				.map(Vec::as_slice)
+				.collect::<Vec<_>>();
+
+			sink.deliver(&channels, format.rate);
+			owed -= frames as f64 / format.rate as f64;
+			rate = Some(format.rate);
+		}
+
+		analyser.advance(config, FRAME_INTERVAL);
+	}
+
+	offscreen.render(analyser.frame())
+		.map_err(SnapshotError::Render)?
+		.save_png(path)
+		.map_err(SnapshotError::Png)
+}
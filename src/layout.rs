@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::graphics::VisualiserVertexVec;
+use crate::visualiser::ChannelMode;
+
+mod obj;
+mod shapes;
+
+pub(crate) use obj::ObjError;
+
+/// Built-in layouts which can be used instead of an OBJ file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Shape {
+	/// The whole surface as a single continuous spectrum
+	Fill,
+	/// Separate bars along the bottom edge
+	Bars,
+	/// Bars mirrored about the centre with the bass in the middle, showing the
+	/// left and right channels on each side in stereo
+	Mirrored,
+	/// Bars around a ring
+	Radial,
+	/// Wedges radiating from the centre
+	Burst,
+	/// The spectrum mirrored about the horizontal centre line, showing the
+	/// left channel above and the right below in stereo
+	Waveform,
+}
+
+impl Shape {
+	/// `stereo` layouts expect the spectrum to hold the left channel followed
+	/// by the right.
+	pub fn generate(self, count: usize, gap: f32, stereo: bool) -> VisualiserVertexVec {
+		match self {
+			Shape::Fill => shapes::fill(),
+			Shape::Bars => shapes::bars(count, gap),
+			Shape::Mirrored => shapes::mirrored(count, gap, stereo),
+			Shape::Radial => shapes::radial(count, gap, 0.5),
+			Shape::Burst => shapes::burst(count, gap),
+			Shape::Waveform => shapes::waveform(stereo),
+		}
+	}
+}
+
+pub fn load(path: &Path) -> Result<VisualiserVertexVec, ObjError> {
+	obj::load(path)
+}
+
+/// Layout settings for a single output, overriding those for every output.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct OutputLayout {
+	pub layout: Option<PathBuf>,
+	pub shape: Option<Shape>,
+	pub bar_count: Option<usize>,
+	pub bar_gap: Option<f32>,
+}
+
+/// Everything which determines a layout.
+#[derive(PartialEq)]
+struct LayoutSettings<'a> {
+	path: Option<&'a Path>,
+	shape: Shape,
+	count: usize,
+	gap: f32,
+	stereo: bool,
+}
+
+impl<'a> LayoutSettings<'a> {
+	fn new(config: &'a Config, output: Option<&str>) -> Self {
+		let overrides = output.and_then(|output| config.outputs.get(output));
+
+		let path = match overrides {
+			// a shape for the output replaces the layout file for every output
+			Some(OutputLayout { layout: None, shape: Some(_), .. }) => None,
+			Some(OutputLayout { layout: Some(path), .. }) => Some(path.as_path()),
+			_ => config.layout.as_deref(),
+		};
+
+		Self {
+			path,
+			shape: overrides.and_then(|o| o.shape).unwrap_or(config.shape),
+			count: overrides.and_then(|o| o.bar_count).unwrap_or(config.bar_count),
+			gap: overrides.and_then(|o| o.bar_gap).unwrap_or(config.bar_gap),
+			stereo: config.channels == ChannelMode::Stereo,
+		}
+	}
+}
+
+/// The layout file given in `config`, or the shape it describes if there is
+/// none, using the settings specific to `output` where there are any.
+pub fn from_config(config: &Config, output: Option<&str>) -> Result<VisualiserVertexVec, ObjError> {
+	let settings = LayoutSettings::new(config, output);
+
+	match settings.path {
+		Some(path) => load(path),
+		None => Ok(settings.shape.generate(settings.count, settings.gap, settings.stereo)),
+	}
+}
+
+/// Whether `from_config` would give the same layout for both configs.
+pub fn unchanged(old: &Config, new: &Config, output: Option<&str>) -> bool {
+	LayoutSettings::new(old, output) == LayoutSettings::new(new, output)
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::graphics::{VisualiserVertex, VisualiserVertexVec};
+
+#[derive(Debug)]
+pub(crate) enum ObjError {
+	Io(io::Error),
+	Syntax {
+		line: usize,
+		reason: SyntaxError,
+	},
+	Empty,
+}
+
+impl fmt::Display for ObjError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ObjError::Io(error) => write!(f, "{}", error),
+			ObjError::Syntax { line, reason } => write!(f, "line {}: {}", line, reason),
+			ObjError::Empty => write!(f, "file contains no faces"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub(crate) enum SyntaxError {
+	MissingValue(&'static str),
+	InvalidNumber(String),
+	InvalidIndex(String),
+	IndexOutOfRange(isize),
+	MissingTextureCoordinate,
+	TooFewVertices(usize),
+}
+
+impl fmt::Display for SyntaxError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SyntaxError::MissingValue(name) => write!(f, "missing {} value", name),
+			SyntaxError::InvalidNumber(token) => write!(f, "{:?} is not a number", token),
+			SyntaxError::InvalidIndex(token) => write!(f, "{:?} is not a valid index", token),
+			SyntaxError::IndexOutOfRange(index) => {
+				write!(f, "index {} refers to an element which has not been defined", index)
+			},
+			SyntaxError::MissingTextureCoordinate => {
+				write!(f, "face vertex has no texture coordinate (frequency/amplitude)")
+			},
+			SyntaxError::TooFewVertices(count) => {
+				write!(f, "face has {} vertices but at least 3 are needed", count)
+			},
+		}
+	}
+}
+
+/// Loads a layout from a Wavefront OBJ file.
+///
+/// Positions come from `v` lines and are in normalised device coordinates
+/// with y pointing up (as most modelling tools expect).
+/// The frequency and amplitude of each vertex come from the `vt` line
+/// referenced by the face: `u` is the frequency and `v` is the amplitude.
+/// Faces with more than three vertices are triangulated as a fan.
+pub fn load(path: &Path) -> Result<VisualiserVertexVec, ObjError> {
+	let file = File::open(path).map_err(ObjError::Io)?;
+	parse(BufReader::new(file))
+}
+
+pub fn parse(reader: impl BufRead) -> Result<VisualiserVertexVec, ObjError> {
+	let mut positions = Vec::new();
+	let mut attributes = Vec::new();
+	let mut vertices = VisualiserVertexVec::new();
+
+	for (index, line) in reader.lines().enumerate() {
+		let line = line.map_err(ObjError::Io)?;
+		let syntax_error = |reason| ObjError::Syntax { line: index + 1, reason };
+
+		let statement = line.split('#').next().unwrap_or_default();
+		let mut tokens = statement.split_whitespace();
+
+		match tokens.next() {
+			Some("v") => {
+				let [x, y] = parse_values(&mut tokens, ["x", "y"])
+					.map_err(syntax_error)?;
+				// vulkan has y pointing down
+				positions.push([x, -y]);
+			},
+			Some("vt") => {
+				let [frequency, amplitude] = parse_values(&mut tokens, ["u", "v"])
+					.map_err(syntax_error)?;
+				attributes.push((frequency, amplitude));
+			},
+			Some("f") => {
+				let corners = tokens
+					.map(|token| parse_corner(token, positions.len(), attributes.len()))
+					.collect::<Result<Vec<_>, _>>()
+					.map_err(syntax_error)?;
+
+				if corners.len() < 3 {
+					return Err(syntax_error(SyntaxError::TooFewVertices(corners.len())));
+				}
+
+				for i in 1..(corners.len() - 1) {
+					for (position, attribute) in [corners[0], corners[i], corners[i + 1]] {
+						let (frequency, amplitude) = attributes[attribute];
+
+						vertices.push(VisualiserVertex {
+							position: positions[position],
+							frequency,
+							amplitude,
+						});
+					}
+				}
+			},
+			// normals, groups, materials, etc. have no meaning for a layout
+			_ => {},
+		}
+	}
+
+	if vertices.is_empty() {
+		return Err(ObjError::Empty);
+	}
+
+	Ok(vertices)
+}
+
+fn parse_values<'a, const N: usize>(
+	tokens: &mut impl Iterator<Item = &'a str>,
+	names: [&'static str; N],
+) -> Result<[f32; N], SyntaxError> {
+	let mut values = [0.0; N];
+
+	for (value, name) in values.iter_mut().zip(names) {
+		let token = tokens.next().ok_or(SyntaxError::MissingValue(name))?;
+		*value = token.parse()
+			.map_err(|_| SyntaxError::InvalidNumber(token.to_owned()))?;
+	}
+
+	Ok(values)
+}
+
+/// Parses a face vertex of the form `v/vt` or `v/vt/vn` into zero-based
+/// position and attribute indices.
+fn parse_corner(
+	token: &str,
+	position_count: usize,
+	attribute_count: usize,
+) -> Result<(usize, usize), SyntaxError> {
+	let mut indices = token.split('/');
+
+	let position = indices.next().unwrap_or_default();
+	let attribute = indices.next()
+		.filter(|index| !index.is_empty())
+		.ok_or(SyntaxError::MissingTextureCoordinate)?;
+
+	Ok((
+		resolve_index(position, position_count)?,
+		resolve_index(attribute, attribute_count)?,
+	))
+}
+
+/// OBJ indices start at 1 and negative values count back from the most
+/// recently defined element.
+fn resolve_index(token: &str, count: usize) -> Result<usize, SyntaxError> {
+	let index: isize = token.parse()
+		.map_err(|_| SyntaxError::InvalidIndex(token.to_owned()))?;
+
+	let resolved = if index > 0 {
+		index - 1
+	} else {
+		count as isize + index
+	};
+
+	if index == 0 || resolved < 0 || resolved >= count as isize {
+		return Err(SyntaxError::IndexOutOfRange(index));
+	}
+
+	Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_str(source: &str) -> Result<VisualiserVertexVec, ObjError> {
+		parse(source.as_bytes())
+	}
+
+	fn syntax_error(source: &str) -> (usize, SyntaxError) {
+		match parse_str(source) {
+			Err(ObjError::Syntax { line, reason }) => (line, reason),
+			other => panic!("expected a syntax error, got {:?}", other.map(|vertices| vertices.len())),
+		}
+	}
+
+	#[test]
+	fn triangle() {
+		let vertices = parse_str("\
+			v 0 0\n\
+			v 1 0.5\n\
+			v -1 -0.5 # comment\n\
+			vt 0.25 1\n\
+			vt 0.75 0\n\
+			f 1/1 2/2 3/1/7\n\
+		").unwrap();
+
+		// y is flipped for vulkan
+		assert_eq!(vertices.position, [[0.0, -0.0], [1.0, -0.5], [-1.0, 0.5]]);
+		assert_eq!(vertices.frequency, [0.25, 0.75, 0.25]);
+		assert_eq!(vertices.amplitude, [1.0, 0.0, 1.0]);
+	}
+
+	#[test]
+	fn fan_triangulation() {
+		let vertices = parse_str("\
+			v 0 0\nv 1 0\nv 1 1\nv 0 1\nv -1 1\n\
+			vt 0 0\n\
+			f 1/1 2/1 3/1 4/1 5/1\n\
+		").unwrap();
+
+		let positions = [[0.0, -0.0], [1.0, -0.0], [1.0, -1.0], [0.0, -1.0], [-1.0, -1.0]];
+		let expected = [0, 1, 2, 0, 2, 3, 0, 3, 4].map(|i| positions[i]);
+
+		assert_eq!(vertices.position, expected);
+	}
+
+	#[test]
+	fn negative_indices() {
+		let vertices = parse_str("\
+			v 0 0\nv 1 0\nv 0 1\n\
+			vt 0.1 0\nvt 0.2 0\n\
+			f -3/-2 -2/-1 -1/-1\n\
+		").unwrap();
+
+		assert_eq!(vertices.position, [[0.0, -0.0], [1.0, -0.0], [0.0, -1.0]]);
+		assert_eq!(vertices.frequency, [0.1, 0.2, 0.2]);
+	}
+
+	#[test]
+	fn negative_indices_are_relative_to_the_line() {
+		// -1 refers to the second vertex, the third isn't defined yet
+		let vertices = parse_str("\
+			v 0 0\nv 1 0\nvt 0 0\n\
+			f 1/1 -1/1 1/1\n\
+			v 5 5\n\
+		").unwrap();
+
+		assert_eq!(vertices.position[1], [1.0, -0.0]);
+	}
+
+	#[test]
+	fn syntax_errors_have_line_numbers() {
+		assert!(matches!(
+			syntax_error("v 0 0\nv 1\n"),
+			(2, SyntaxError::MissingValue("y")),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\n\nvt a 0\n"),
+			(3, SyntaxError::InvalidNumber(token)) if token == "a",
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\nf 1/1 1/1\n"),
+			(3, SyntaxError::TooFewVertices(2)),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\nf 1/1 1/1 1\n"),
+			(3, SyntaxError::MissingTextureCoordinate),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\n# comment\nf 1/1 1/1 2/1\n"),
+			(4, SyntaxError::IndexOutOfRange(2)),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\nf 0/1 1/1 1/1\n"),
+			(3, SyntaxError::IndexOutOfRange(0)),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\nf 1/1 -2/1 1/1\n"),
+			(3, SyntaxError::IndexOutOfRange(-2)),
+		));
+		assert!(matches!(
+			syntax_error("v 0 0\nvt 0 0\nf 1/1 x/1 1/1\n"),
+			(3, SyntaxError::InvalidIndex(token)) if token == "x",
+		));
+	}
+
+	#[test]
+	fn no_faces() {
+		assert!(matches!(parse_str("v 0 0\nvt 0 0\n"), Err(ObjError::Empty)));
+	}
+}
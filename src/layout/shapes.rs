@@ -0,0 +1,313 @@
+use std::f32::consts::TAU;
+
+use crate::graphics::{VisualiserVertex, VisualiserVertexVec};
+
+fn vertex(position: [f32; 2], frequency: f32, amplitude: f32) -> VisualiserVertex {
+	VisualiserVertex { position, frequency, amplitude }
+}
+
+/// Pushes two triangles covering the quad with corners given in order
+/// around its edge.
+fn push_quad(vertices: &mut VisualiserVertexVec, [a, b, c, d]: [VisualiserVertex; 4]) {
+	vertices.push(a);
+	vertices.push(b);
+	vertices.push(c);
+	vertices.push(a);
+	vertices.push(c);
+	vertices.push(d);
+}
+
+/// Splits the range 0..1 into `count` equal slots and returns the start and
+/// end of the visible part of each, leaving `gap` (as a fraction of the slot)
+/// empty and centred between neighbours.
+fn slots(count: usize, gap: f32) -> impl Iterator<Item = (f32, f32, f32)> {
+	let width = 1.0 / count as f32;
+	let margin = width * gap.clamp(0.0, 1.0) / 2.0;
+
+	(0..count).map(move |i| {
+		let start = i as f32 * width;
+		let frequency = start + width / 2.0;
+
+		(start + margin, start + width - margin, frequency)
+	})
+}
+
+fn polar(radius: f32, turn: f32) -> [f32; 2] {
+	let angle = turn * TAU;
+	[radius * angle.sin(), -radius * angle.cos()]
+}
+
+/// A rectangle covering the whole surface with the spectrum running from left
+/// to right and amplitude increasing upwards.
+pub fn fill() -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(6);
+
+	push_quad(&mut vertices, [
+		vertex([-1.0, 1.0], 0.0, 0.0),
+		vertex([1.0, 1.0], 1.0, 0.0),
+		vertex([1.0, -1.0], 1.0, 1.0),
+		vertex([-1.0, -1.0], 0.0, 1.0),
+	]);
+
+	vertices
+}
+
+/// `count` bars along the bottom edge, each showing a single frequency.
+pub fn bars(count: usize, gap: f32) -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(count * 6);
+
+	for (start, end, frequency) in slots(count, gap) {
+		let left = start * 2.0 - 1.0;
+		let right = end * 2.0 - 1.0;
+
+		push_quad(&mut vertices, [
+			vertex([left, 1.0], frequency, 0.0),
+			vertex([right, 1.0], frequency, 0.0),
+			vertex([right, -1.0], frequency, 1.0),
+			vertex([left, -1.0], frequency, 1.0),
+		]);
+	}
+
+	vertices
+}
+
+/// `count` bars on each side of the centre with the lowest frequencies in the
+/// middle, growing both up and down from the horizontal centre line.
+/// If `stereo` the first half of the spectrum goes on the left and the second
+/// half on the right, otherwise both sides show the whole spectrum.
+pub fn mirrored(count: usize, gap: f32, stereo: bool) -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(count * 24);
+
+	let (left, right) = if stereo {
+		((0.0, 0.5), (0.5, 0.5))
+	} else {
+		((0.0, 1.0), (0.0, 1.0))
+	};
+
+	for (start, end, frequency) in slots(count, gap) {
+		let sides = [
+			(-start, -end, left.0 + frequency * left.1),
+			(start, end, right.0 + frequency * right.1),
+		];
+
+		for (inner, outer, frequency) in sides {
+			for edge in [-1.0, 1.0] {
+				push_quad(&mut vertices, [
+					vertex([inner, 0.0], frequency, 0.0),
+					vertex([outer, 0.0], frequency, 0.0),
+					vertex([outer, edge], frequency, 1.0),
+					vertex([inner, edge], frequency, 1.0),
+				]);
+			}
+		}
+	}
+
+	vertices
+}
+
+/// The whole surface as one continuous spectrum mirrored about the
+/// horizontal centre line, growing both up and down like a waveform.
+/// If `stereo` the top half shows the first half of the spectrum and the
+/// bottom half the second, otherwise both show the whole spectrum.
+pub fn waveform(stereo: bool) -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(12);
+
+	let halves = if stereo {
+		[(-1.0, 0.0, 0.5), (1.0, 0.5, 1.0)]
+	} else {
+		[(-1.0, 0.0, 1.0), (1.0, 0.0, 1.0)]
+	};
+
+	for (edge, low, high) in halves {
+		push_quad(&mut vertices, [
+			vertex([-1.0, 0.0], low, 0.0),
+			vertex([1.0, 0.0], high, 0.0),
+			vertex([1.0, edge], high, 1.0),
+			vertex([-1.0, edge], low, 1.0),
+		]);
+	}
+
+	vertices
+}
+
+/// `count` bars arranged clockwise around a circle, starting at the top and
+/// growing outwards from `inner_radius` to the edge of the surface.
+pub fn radial(count: usize, gap: f32, inner_radius: f32) -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(count * 6);
+
+	for (start, end, frequency) in slots(count, gap) {
+		push_quad(&mut vertices, [
+			vertex(polar(inner_radius, start), frequency, 0.0),
+			vertex(polar(inner_radius, end), frequency, 0.0),
+			vertex(polar(1.0, end), frequency, 1.0),
+			vertex(polar(1.0, start), frequency, 1.0),
+		]);
+	}
+
+	vertices
+}
+
+/// `count` wedges radiating from the centre of the surface.
+/// Wedges of half a turn or more are split into several triangles, as a
+/// single one would have no area.
+pub fn burst(count: usize, gap: f32) -> VisualiserVertexVec {
+	let mut vertices = VisualiserVertexVec::with_capacity(count * 3);
+
+	for (start, end, frequency) in slots(count, gap) {
+		let pieces = ((end - start) * 2.0).floor() as usize + 1;
+		let span = (end - start) / pieces as f32;
+
+		for piece in 0..pieces {
+			let start = start + piece as f32 * span;
+
+			vertices.push(vertex([0.0, 0.0], frequency, 0.0));
+			vertices.push(vertex(polar(1.0, start), frequency, 1.0));
+			vertices.push(vertex(polar(1.0, start + span), frequency, 1.0));
+		}
+	}
+
+	vertices
+}
+
+#[cfg(test)]
+mod tests {
+	fn assert_close(actual: &[[f32; 2]], expected: &[[f32; 2]]) {
+		assert_eq!(actual.len(), expected.len());
+
+		for (actual, expected) in actual.iter().zip(expected) {
+			assert!(
+				(actual[0] - expected[0]).abs() < 1e-6 && (actual[1] - expected[1]).abs() < 1e-6,
+				"{:?} != {:?}", actual, expected,
+			);
+		}
+	}
+
+	#[test]
+	fn fill() {
+		let vertices = super::fill();
+
+		assert_eq!(vertices.position, [
+			[-1.0, 1.0], [1.0, 1.0], [1.0, -1.0],
+			[-1.0, 1.0], [1.0, -1.0], [-1.0, -1.0],
+		]);
+		assert_eq!(vertices.frequency, [0.0, 1.0, 1.0, 0.0, 1.0, 0.0]);
+		assert_eq!(vertices.amplitude, [0.0, 0.0, 1.0, 0.0, 1.0, 1.0]);
+	}
+
+	#[test]
+	fn bars() {
+		let vertices = super::bars(2, 0.5);
+
+		assert_close(&vertices.position, &[
+			[-0.75, 1.0], [-0.25, 1.0], [-0.25, -1.0],
+			[-0.75, 1.0], [-0.25, -1.0], [-0.75, -1.0],
+			[0.25, 1.0], [0.75, 1.0], [0.75, -1.0],
+			[0.25, 1.0], [0.75, -1.0], [0.25, -1.0],
+		]);
+		assert_eq!(vertices.frequency, [[0.25; 6], [0.75; 6]].concat());
+		assert_eq!(vertices.amplitude, [[0.0, 0.0, 1.0, 0.0, 1.0, 1.0]; 2].concat());
+	}
+
+	#[test]
+	fn mirrored() {
+		let mono = super::mirrored(1, 0.0, false);
+		let stereo = super::mirrored(1, 0.0, true);
+
+		// both sides, each growing up and down
+		assert_eq!(mono.len(), 24);
+		assert!(mono.position.iter().all(|[x, _]| x.abs() <= 1.0));
+		assert!(mono.position[..12].iter().all(|[x, _]| *x <= 0.0));
+		assert!(mono.position[12..].iter().all(|[x, _]| *x >= 0.0));
+
+		assert_eq!(mono.frequency, [0.5; 24]);
+		assert_eq!(stereo.frequency, [[0.25; 12], [0.75; 12]].concat());
+
+		// the amplitude is 0 along the centre line and 1 at the edges
+		for (&[_, y], &amplitude) in mono.position.iter().zip(&mono.amplitude) {
+			assert_eq!(amplitude, y.abs());
+		}
+	}
+
+	#[test]
+	fn waveform() {
+		let mono = super::waveform(false);
+		let stereo = super::waveform(true);
+
+		assert_eq!(mono.position, [
+			[-1.0, 0.0], [1.0, 0.0], [1.0, -1.0],
+			[-1.0, 0.0], [1.0, -1.0], [-1.0, -1.0],
+			[-1.0, 0.0], [1.0, 0.0], [1.0, 1.0],
+			[-1.0, 0.0], [1.0, 1.0], [-1.0, 1.0],
+		]);
+		assert_eq!(mono.frequency, [[0.0, 1.0, 1.0, 0.0, 1.0, 0.0]; 2].concat());
+		assert_eq!(mono.amplitude, [[0.0, 0.0, 1.0, 0.0, 1.0, 1.0]; 2].concat());
+
+		assert_eq!(stereo.position, mono.position);
+		assert_eq!(stereo.frequency, [
+			0.0, 0.5, 0.5, 0.0, 0.5, 0.0,
+			0.5, 1.0, 1.0, 0.5, 1.0, 0.5,
+		]);
+	}
+
+	#[test]
+	fn radial() {
+		let vertices = super::radial(4, 0.0, 0.5);
+
+		assert_eq!(vertices.len(), 24);
+		assert_eq!(vertices.frequency, [[0.125; 6], [0.375; 6], [0.625; 6], [0.875; 6]].concat());
+
+		// the first bar starts at the top and goes clockwise
+		assert_close(&vertices.position[..6], &[
+			[0.0, -0.5], [0.5, 0.0], [1.0, 0.0],
+			[0.0, -0.5], [1.0, 0.0], [0.0, -1.0],
+		]);
+
+		for (&[x, y], &amplitude) in vertices.position.iter().zip(&vertices.amplitude) {
+			let radius = if amplitude == 0.0 { 0.5 } else { 1.0 };
+			assert!((x.hypot(y) - radius).abs() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn burst() {
+		let vertices = super::burst(4, 0.0);
+
+		assert_close(&vertices.position, &[
+			[0.0, 0.0], [0.0, -1.0], [1.0, 0.0],
+			[0.0, 0.0], [1.0, 0.0], [0.0, 1.0],
+			[0.0, 0.0], [0.0, 1.0], [-1.0, 0.0],
+			[0.0, 0.0], [-1.0, 0.0], [0.0, -1.0],
+		]);
+		assert_eq!(vertices.frequency, [[0.125; 3], [0.375; 3], [0.625; 3], [0.875; 3]].concat());
+		assert_eq!(vertices.amplitude, [[0.0, 1.0, 1.0]; 4].concat());
+	}
+
+	#[test]
+	fn burst_splits_wide_wedges() {
+		let vertices = super::burst(2, 0.0);
+
+		// each half turn is two quarter turns
+		assert_close(&vertices.position, &[
+			[0.0, 0.0], [0.0, -1.0], [1.0, 0.0],
+			[0.0, 0.0], [1.0, 0.0], [0.0, 1.0],
+			[0.0, 0.0], [0.0, 1.0], [-1.0, 0.0],
+			[0.0, 0.0], [-1.0, 0.0], [0.0, -1.0],
+		]);
+		assert_eq!(vertices.frequency, [[0.25; 6], [0.75; 6]].concat());
+
+		for count in 1..=8 {
+			let vertices = super::burst(count, 0.0);
+
+			// wound clockwise on screen with y down, so every area is positive
+			for triangle in vertices.position.chunks(3) {
+				let [[ax, ay], [bx, by], [cx, cy]] = [triangle[0], triangle[1], triangle[2]];
+				let area = (bx - ax) * (cy - ay) - (by - ay) * (cx - ax);
+
+				assert!(area > 1e-3, "{} wedges: {:?} has no area", count, triangle);
+			}
+		}
+
+		assert_eq!(super::burst(1, 0.0).len(), 9);
+		assert_eq!(super::burst(3, 0.0).len(), 9);
+	}
+}
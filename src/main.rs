@@ -4,44 +4,241 @@ mod graphics;
 mod window;
 mod audio;
 mod visualiser;
+mod layout;
+mod config;
+mod headless;
 
-use std::{sync::{Arc, RwLock}, path::PathBuf};
+use std::{sync::{Arc, RwLock, mpsc}, path::PathBuf, time::Duration};
 
+use clap::{CommandFactory, FromArgMatches, Parser};
+
+use audio::{AudioFormat, AudioReader, AudioSink, AudioSource, FileOptions, FileReader, FileSource, PcmFormat, PcmReader, PcmSource, TargetSender, Tone};
 use window::Window;
 use visualiser::BufferManager;
+use config::{Config, ConfigSource};
+use graphics::ShaderCode;
 
 const BUFFER_SIZE: usize = 512;
 
-use clap::Parser;
-
 #[derive(Debug, Parser)]
 struct Arguments {
-	/// Shift the spectrum to show more detail at the lower frequencies at
-	/// values greater than 1 and higher frequencies at less than 1.
-	#[arg(short, long, default_value_t = 1.02)]
-	power_scale_frequencies: f32,
-	/// Clip the spectrum to have this frequency be the highest pitch
-	#[arg(short, long, default_value_t = 15000.0)]
-	ceiling_frequency: f32,
-	/// Clip the spectrum to have this frequency be the lowest pitch
-	#[arg(short, long, default_value_t = 0.0)]
-	floor_frequency: f32,
-	/// Multiply the output levels by the value
-	#[arg(short, long, default_value_t = 1.0)]
-	scale: f32,
-	/// Path to the obj file to use for displaying data
-	layout: Option<PathBuf>, 
-}
-
-lazy_static::lazy_static! {
-	static ref CONFIG: Arguments = Arguments::parse();
+	/// Read settings from this TOML file instead of
+	/// ~/.config/visualiser/config.toml, options given on the command line
+	/// take precedence over it
+	#[arg(long, value_name = "PATH")]
+	config: Option<PathBuf>,
+	/// List the audio nodes which can be used as a target and exit
+	#[cfg(feature = "pipewire")]
+	#[arg(long)]
+	list_nodes: bool,
+	/// Render a single frame offscreen to this PNG file and exit, which needs
+	/// no compositor. The audio is read from --file or --pcm as fast as it
+	/// can be, or is a fixed test tone without them
+	#[arg(long, value_name = "PATH")]
+	snapshot: Option<PathBuf>,
+	/// Milliseconds of audio to analyse before taking the snapshot
+	#[arg(long, value_name = "MS", default_value_t = 1000)]
+	snapshot_delay: u64,
+	/// Visualise this audio file (WAV, FLAC or Ogg Vorbis) in real time
+	/// instead of capturing audio
+	#[arg(long, value_name = "PATH", conflicts_with = "pcm")]
+	file: Option<PathBuf>,
+	/// Start the file this many seconds in
+	#[arg(long, value_name = "SECONDS", default_value = "0", value_parser = parse_seconds, requires = "file")]
+	seek: Duration,
+	/// Start the file again from the beginning when it ends
+	#[arg(long = "loop", requires = "file")]
+	looping: bool,
+	/// Play the file through the default output as it is visualised
+	#[arg(long, requires = "file", conflicts_with = "snapshot")]
+	play: bool,
+	/// Read raw interleaved PCM from this file or named pipe (such as MPD's
+	/// fifo output), or - for stdin, instead of capturing audio
+	#[arg(long, value_name = "PATH")]
+	pcm: Option<PathBuf>,
+	/// Sample rate of the PCM input
+	#[arg(long, value_name = "HZ", default_value_t = 44100, value_parser = clap::value_parser!(u32).range(1..), requires = "pcm")]
+	pcm_rate: u32,
+	/// Number of channels in the PCM input
+	#[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..), requires = "pcm")]
+	pcm_channels: u32,
+	/// Sample format of the PCM input
+	#[arg(long, value_enum, default_value_t = PcmFormat::S16le, requires = "pcm")]
+	pcm_format: PcmFormat,
+	#[command(flatten)]
+	settings: Config,
 }
 
 fn main() {
-	let buffer_manager = Arc::new(RwLock::new(BufferManager::default()));
+	let matches = Arguments::command().get_matches();
+	let arguments = Arguments::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+	#[cfg(feature = "pipewire")]
+	if arguments.list_nodes {
+		if let Err(error) = audio::list_nodes() {
+			eprintln!("Failed to list audio nodes: {}", error);
+			std::process::exit(1);
+		}
+
+		return;
+	}
+
+	let source = ConfigSource::new(arguments.config.clone(), &matches, &arguments.settings);
+
+	let config = source.load().unwrap_or_else(|error| {
+		eprintln!("Failed to load config {}: {}", source.path().display(), error);
+		std::process::exit(1);
+	});
+
+	// layouts for specific outputs are loaded once the outputs are known
+	let layout = layout::from_config(&config, None).unwrap_or_else(|error| {
+		eprintln!("Failed to load layout: {}", error);
+		std::process::exit(1);
+	});
+
+	let shaders = ShaderCode::load(&config).unwrap_or_else(|error| {
+		eprintln!("Failed to load shaders: {}", error);
+		std::process::exit(1);
+	});
+
+	if let Some(path) = &arguments.snapshot {
+		let mut audio = snapshot_audio(&arguments);
+		let delay = Duration::from_millis(arguments.snapshot_delay);
+
+		if let Err(error) = headless::snapshot(path, &config, &layout, &shaders, &mut *audio, delay) {
+			eprintln!("Failed to take snapshot: {}", error);
+			std::process::exit(1);
+		}
+
+		return;
+	}
 
-	audio::main(Arc::clone(&buffer_manager));
+	let buffer_manager = Arc::new(RwLock::new(BufferManager::new(config.clone())));
 
-	let mut window = Window::new(buffer_manager);
+	let (mut input, target_sender) = audio_source(&arguments, &config);
+
+	input.start(AudioSink::new(Arc::clone(&buffer_manager))).unwrap_or_else(|error| {
+		eprintln!("Failed to start the audio input: {}", error);
+		std::process::exit(1);
+	});
+
+	let (config_sender, config_receiver) = mpsc::channel();
+
+	let _watcher = source.watch(config.clone(), {
+		let mut target = config.target.clone();
+		move |config: &Config| {
+			// only a capture has a target to change
+			if let Some(target_sender) = target_sender.as_ref().filter(|_| config.target != target) {
+				target = config.target.clone();
+				target_sender.send(target.as_deref());
+			}
+
+			let _ = config_sender.send(config.clone());
+		}
+	}).unwrap_or_else(|error| {
+		eprintln!("Not watching the config for changes: {}", error);
+		None
+	});
+
+	let mut window = Window::new(buffer_manager, config, config_receiver, shaders);
 	window.run();
+
+	input.stop();
+}
+
+/// Opens the file or PCM input to take a snapshot of if one was given,
+/// otherwise a test tone. Nothing is captured, as live audio would make every
+/// snapshot different.
+fn snapshot_audio(arguments: &Arguments) -> Box<dyn AudioReader> {
+	if let Some(path) = &arguments.file {
+		let reader = FileReader::open(path, file_options(arguments)).unwrap_or_else(|error| {
+			eprintln!("Failed to open {}: {}", path.display(), error);
+			std::process::exit(1);
+		});
+
+		return Box::new(reader);
+	}
+
+	if let Some(path) = &arguments.pcm {
+		let reader = PcmReader::open(path, pcm_format(arguments), arguments.pcm_format).unwrap_or_else(|error| {
+			eprintln!("Failed to open {}: {}", path.display(), error);
+			std::process::exit(1);
+		});
+
+		return Box::new(reader);
+	}
+
+	Box::new(Tone::new())
+}
+
+/// Opens the file or PCM input to visualise if one was given, otherwise
+/// captures from the configured target. Only captures can be moved to another
+/// target.
+fn audio_source(arguments: &Arguments, config: &Config) -> (Box<dyn AudioSource>, Option<TargetSender>) {
+	if let Some(path) = &arguments.file {
+		let source = FileSource::open(path, file_options(arguments)).unwrap_or_else(|error| {
+			eprintln!("Failed to open {}: {}", path.display(), error);
+			std::process::exit(1);
+		});
+
+		return (Box::new(source), None);
+	}
+
+	if let Some(path) = &arguments.pcm {
+		let source = PcmSource::new(path.clone(), pcm_format(arguments), arguments.pcm_format);
+
+		return (Box::new(source), None);
+	}
+
+	capture(config)
+}
+
+fn file_options(arguments: &Arguments) -> FileOptions {
+	FileOptions {
+		start: arguments.seek,
+		looping: arguments.looping,
+		play: arguments.play,
+	}
+}
+
+/// Parses a position in seconds, which has to be finite and not negative.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+	let seconds = value.parse::<f64>().map_err(|error| error.to_string())?;
+
+	Duration::try_from_secs_f64(seconds)
+		.map_err(|_| format!("{} is not a position in seconds", value))
+}
+
+fn pcm_format(arguments: &Arguments) -> AudioFormat {
+	AudioFormat {
+		rate: arguments.pcm_rate,
+		channels: arguments.pcm_channels,
+	}
+}
+
+#[cfg(feature = "pipewire")]
+fn capture(config: &Config) -> (Box<dyn AudioSource>, Option<TargetSender>) {
+	let target = config.target.as_ref().map(|target| {
+		match audio::find_node(target) {
+			Ok(Some(node)) => node,
+			Ok(None) => {
+				eprintln!("No audio node matches {:?}, see --list-nodes for those available", target);
+				std::process::exit(1);
+			},
+			Err(error) => {
+				eprintln!("Failed to look up audio node {:?}: {}", target, error);
+				std::process::exit(1);
+			},
+		}
+	});
+
+	let (source, target_sender) = audio::PipeWireSource::new(target);
+
+	(Box::new(source), Some(target_sender))
+}
+
+#[cfg(not(feature = "pipewire"))]
+fn capture(_config: &Config) -> (Box<dyn AudioSource>, Option<TargetSender>) {
+	eprintln!("Built without PipeWire support, so --file or --pcm is needed");
+	std::process::exit(1);
 }
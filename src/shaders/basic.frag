@@ -1,6 +1,51 @@
 #version 450
 
+// must match MAX_STOPS in src/graphics/colours.rs
+#define MAX_STOPS 16
+
+#define SCHEME_RAINBOW 0u
+#define SCHEME_SOLID 1u
+#define SCHEME_FREQUENCY 2u
+#define SCHEME_AMPLITUDE 3u
+
+// must match Scroll in src/graphics/spectrogram.rs
+#define SCROLL_UP 0u
+#define SCROLL_DOWN 1u
+#define SCROLL_LEFT 2u
+#define SCROLL_RIGHT 3u
+
+layout (constant_id = 0) const uint SCHEME = SCHEME_RAINBOW;
+layout (constant_id = 1) const uint STOP_COUNT = 1u;
+layout (constant_id = 2) const bool PREMULTIPLIED = false;
+layout (constant_id = 3) const bool SPECTROGRAM = false;
+layout (constant_id = 4) const uint SCROLL = SCROLL_UP;
+
 layout (binding = 0) uniform sampler1D frequency_magnitude;
+layout (binding = 1) uniform sampler1D peak_magnitude;
+
+layout (binding = 2) uniform Colours {
+	// already premultiplied where needed
+	vec4 background;
+	vec4 stop_colours[MAX_STOPS];
+	vec4 stop_positions[MAX_STOPS];
+} colours;
+
+layout (binding = 3) uniform FrameInfo {
+	float time;
+	float delta;
+	vec2 resolution;
+	float rms;
+	float peak;
+	float bass;
+	float mid;
+	float treble;
+	bool beat;
+	uint spectrogram_row;
+	uint audio_state;
+} frame;
+
+// one spectrum per row, the newest in frame.spectrogram_row
+layout (binding = 5) uniform sampler2D spectrogram;
 
 layout (location = 0) in float frag_frequency;
 layout (location = 1) in float target_amplitude;
@@ -16,12 +61,101 @@ vec3 hsv_to_rgb(float h) {
     return hsv.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), hsv.y);
 }
 
+vec4 gradient(float position) {
+	vec4 colour = colours.stop_colours[0];
+
+	for (uint i = 1; i < STOP_COUNT; i++) {
+		float start = colours.stop_positions[i - 1].x;
+		float end = colours.stop_positions[i].x;
+
+		if (position > start) {
+			float t = clamp((position - start) / max(end - start, 1e-6), 0.0, 1.0);
+			colour = mix(colours.stop_colours[i - 1], colours.stop_colours[i], t);
+		}
+	}
+
+	return colour;
+}
+
+vec4 foreground() {
+	switch (SCHEME) {
+		case SCHEME_SOLID:
+			return colours.stop_colours[0];
+		case SCHEME_FREQUENCY:
+			return gradient(frag_frequency);
+		case SCHEME_AMPLITUDE:
+			return gradient(target_amplitude);
+		default:
+			return vec4(hsv_to_rgb(frag_frequency), 1.0);
+	}
+}
+
+vec4 premultiply(vec4 colour) {
+	return PREMULTIPLIED ? vec4(colour.rgb * colour.a, colour.a) : colour;
+}
+
+// Colours a level from the spectrogram, fading in from the background for
+// the schemes which don't already map levels to colours.
+vec4 level_colour(float level, float frequency) {
+	switch (SCHEME) {
+		case SCHEME_SOLID:
+			return mix(colours.background, premultiply(colours.stop_colours[0]), level);
+		case SCHEME_FREQUENCY:
+			return mix(colours.background, premultiply(gradient(frequency)), level);
+		case SCHEME_AMPLITUDE:
+			return premultiply(gradient(level));
+		default:
+			return mix(colours.background, premultiply(vec4(hsv_to_rgb(level), 1.0)), level);
+	}
+}
+
+vec4 spectrogram_colour() {
+	// how far back in the history the fragment is, from 0 for the newest
+	float frequency = frag_frequency;
+	float age = target_amplitude;
+
+	switch (SCROLL) {
+		case SCROLL_DOWN:
+			age = 1.0 - target_amplitude;
+			break;
+		case SCROLL_LEFT:
+			frequency = target_amplitude;
+			age = 1.0 - frag_frequency;
+			break;
+		case SCROLL_RIGHT:
+			frequency = target_amplitude;
+			age = frag_frequency;
+			break;
+		default:
+			break;
+	}
+
+	ivec2 size = textureSize(spectrogram, 0);
+	int rows_back = clamp(int(age * float(size.y)), 0, size.y - 1);
+	int row = (int(frame.spectrogram_row) - rows_back + size.y) % size.y;
+	int column = clamp(int(frequency * float(size.x)), 0, size.x - 1);
+
+	float level = clamp(texelFetch(spectrogram, ivec2(column, row), 0).r, 0.0, 1.0);
+
+	return level_colour(level, frequency);
+}
+
 void main() {
+	if (SPECTROGRAM) {
+		color = spectrogram_colour();
+		return;
+	}
+
 	float amplitude = texture(frequency_magnitude, frag_frequency).r;
+	float peak = texture(peak_magnitude, frag_frequency).r;
 
-	if (amplitude > target_amplitude) {
-		color = vec4(hsv_to_rgb(frag_frequency), 1.0);
+	// a line roughly two pixels thick at the held peak
+	if (peak > 0.0 && abs(peak - target_amplitude) < 2.0 * fwidth(target_amplitude)) {
+		vec4 colour = foreground();
+		color = premultiply(vec4(mix(colour.rgb, vec3(1.0), 0.5), colour.a));
+	} else if (amplitude > target_amplitude) {
+		color = premultiply(foreground());
 	} else {
-		color = vec4(0.0, 0.0, 0.0, 0.0);
+		color = colours.background;
 	}
-}
\ No newline at end of file
+}
@@ -1,36 +1,149 @@
 use std::time::Duration;
 use std::collections::{VecDeque, HashMap};
 
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use enterpolation::{linear::Linear, Curve};
 use rustfft::{FftDirection, Fft};
 use rustfft::algorithm::Radix4;
 use rustfft::num_complex::Complex;
 
-use crate::CONFIG;
+use crate::BUFFER_SIZE;
+use crate::audio::SourceState;
+use crate::config::Config;
+
+use self::constant_q::ConstantQ;
+use self::levels::LevelMeter;
+use self::ring_buffer::RingBuffer;
+
+pub(crate) use self::analyser::Analyser;
+pub(crate) use self::smoothing::Smoothing;
+pub(crate) use self::gain::AutoGain;
+pub(crate) use self::levels::{BeatDetector, Levels};
+
+mod analyser;
+mod constant_q;
+mod gain;
+mod idle;
+mod levels;
+mod ring_buffer;
+mod smoothing;
 
 const BUFFER_TARGET: usize = 3;
 
+/// How the channels of the audio stream are turned into spectra.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ChannelMode {
+	/// Average all channels into a single spectrum
+	Mono,
+	/// Separate spectra for the left and right channels
+	Stereo,
+	/// A separate spectrum for every channel in the stream
+	Separate,
+}
+
+impl ChannelMode {
+	fn mix(self, channels: &[&[f32]]) -> Box<[Box<[f32]>]> {
+		match self {
+			ChannelMode::Mono => {
+				let length = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+				let scale = 1.0 / channels.len() as f32;
+
+				let mixed = (0..length)
+					.map(|i| channels.iter().map(|c| c[i]).sum::<f32>() * scale)
+					.collect();
+
+				Box::new([mixed])
+			},
+			ChannelMode::Stereo => match channels {
+				[] => Box::new([]),
+				[mono] => Box::new([Box::from(*mono), Box::from(*mono)]),
+				[left, right, ..] => Box::new([Box::from(*left), Box::from(*right)]),
+			},
+			ChannelMode::Separate => channels.iter()
+				.map(|c| Box::from(*c))
+				.collect(),
+		}
+	}
+}
+
+/// How the audio is turned into a spectrum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Analysis {
+	/// A single FFT resampled to spread out the lower frequencies
+	Fft,
+	/// Logarithmically spaced bins, each with its own window length
+	ConstantQ,
+}
+
+/// Everything the renderer needs to draw a frame.
+pub(crate) struct Frame {
+	pub spectrum: Box<[f32; BUFFER_SIZE]>,
+	pub peaks: Box<[f32; BUFFER_SIZE]>,
+	/// the latest samples of the first two channels, oldest first
+	pub waveform: Box<[[f32; 2]; BUFFER_SIZE]>,
+	pub levels: Levels,
+	/// seconds since the first frame
+	pub time: f32,
+	/// seconds since the previous frame
+	pub delta: f32,
+	/// analysis steps so far, each adds a row to the spectrogram
+	pub step: u64,
+	pub source_state: SourceState,
+}
+
+impl Frame {
+	pub fn empty() -> Self {
+		Self {
+			spectrum: Box::new([0.0; BUFFER_SIZE]),
+			peaks: Box::new([0.0; BUFFER_SIZE]),
+			waveform: Box::new([[0.0; 2]; BUFFER_SIZE]),
+			levels: Levels::default(),
+			time: 0.0,
+			delta: 0.0,
+			step: 0,
+			source_state: SourceState::default(),
+		}
+	}
+}
+
 struct AudioBuffer {
-	data: Box<[f32]>,
+	channels: Box<[Box<[f32]>]>,
+	/// the first two channels as they arrived, whatever the channel mode
+	scope: Box<[Box<[f32]>]>,
 	position: usize,
 	rate: f32,
 }
 
 impl AudioBuffer {
-	fn read(&mut self, duration: Duration) -> (&[f32], Duration) {
+	fn len(&self) -> usize {
+		self.channels.iter().chain(self.scope.iter()).map(|c| c.len()).min().unwrap_or(0)
+	}
+
+	/// The channels and scope channels covering `duration`, and how long they
+	/// actually cover.
+	fn read(&mut self, duration: Duration) -> (Vec<&[f32]>, Vec<&[f32]>, Duration) {
 		let desired_read_count = (duration.as_secs_f32() * self.rate).floor() as usize;
 		
-		let max_read_count = self.data.len() - self.position;
+		let max_read_count = self.len() - self.position;
 		let values_to_read = usize::min(max_read_count, desired_read_count);
 
 		let elapsed = Duration::from_secs_f32((values_to_read) as f32 / self.rate);
 		let next_position = self.position + values_to_read;
 
-		let data = &self.data[self.position..next_position];
+		let data = self.channels.iter()
+			.map(|channel| &channel[self.position..next_position])
+			.collect();
+
+		let scope = self.scope.iter()
+			.map(|channel| &channel[self.position..next_position])
+			.collect();
 
 		self.position = next_position;
 
-		(data, elapsed)
+		(data, scope, elapsed)
 	}
 }
 
@@ -45,16 +158,42 @@ pub(crate) struct BufferManager {
 	buffers: VecDeque<AudioBuffer>,
 	/// key is the power to raise 2 to for the radix size
 	ffts: HashMap<u8, FftCache>,
+	/// key is the power to raise 2 to for the size, the rate, and the bin count
+	constant_qs: HashMap<(u8, u32, usize), ConstantQ>,
+	/// the most recent samples of each channel
+	history: Vec<RingBuffer>,
+	/// the most recent samples of the first two channels before they were
+	/// mixed, for the waveform
+	scope: Vec<RingBuffer>,
+	/// number of samples added to the history since it was last analysed
+	unanalysed: usize,
+	meter: LevelMeter,
+	/// levels of the samples consumed since they were last taken
+	levels: Option<Levels>,
+	source_state: SourceState,
+	config: Config,
 }
 
 struct BufferSlice {
-	values: Vec<f32>,
+	channels: Vec<Vec<f32>>,
+	scope: Vec<Vec<f32>>,
 	rate: f32,
 }
 
 impl BufferManager {
+	pub fn new(config: Config) -> Self {
+		Self { config, ..Self::default() }
+	}
+
+	pub fn set_config(&mut self, config: Config) {
+		// the transforms depend on the frequency range
+		self.constant_qs.clear();
+		self.config = config;
+	}
+
 	fn take_next(&mut self, interval: Duration) -> BufferSlice {
-		let mut values = Vec::new();
+		let mut channels: Vec<Vec<f32>> = Vec::new();
+		let mut scope: Vec<Vec<f32>> = Vec::new();
 		let mut buffers_taken = 0;
 		let mut rate = 0.0;
 		let mut remaining_interval = interval;
@@ -62,11 +201,26 @@ impl BufferManager {
 
 		for buffer in &mut self.buffers {
 			let buffer_rate = buffer.rate;
-			let (slice, elapsed) = buffer.read(remaining_interval);
+			let (slices, scope_slices, elapsed) = buffer.read(remaining_interval);
 
 			rate += buffer_rate * elapsed.as_secs_f32() / interval;
 
-			values.extend_from_slice(slice);
+			if channels.len() < slices.len() {
+				channels.resize_with(slices.len(), Vec::new);
+			}
+
+			for (values, slice) in channels.iter_mut().zip(slices) {
+				values.extend_from_slice(slice);
+			}
+
+			if scope.len() < scope_slices.len() {
+				scope.resize_with(scope_slices.len(), Vec::new);
+			}
+
+			for (values, slice) in scope.iter_mut().zip(scope_slices) {
+				values.extend_from_slice(slice);
+			}
+
 			remaining_interval = remaining_interval.saturating_sub(elapsed);
 
 			// why not is_zero?: because floating point imprecision and rounding
@@ -83,22 +237,131 @@ impl BufferManager {
 
 		self.buffers.drain(0..buffers_taken);
 
-		BufferSlice { values, rate }
+		BufferSlice { channels, scope, rate }
 	}
 
-	// TODO: would be nice to have constant_q and/or variable_q intervals
-
+	/// Consumes the audio covering `interval` and produces the spectra for the
+	/// most recent samples, overlapping with the previous analysis.
+	/// Returns `None` if less than the hop size has been consumed since the last
+	/// analysis.
+	/// With more than one channel the spectra are placed side by side, each
+	/// taking an equal share of the `T` values.
 	pub fn fft_interval<const T: usize>(
 		&mut self,
 		interval: Duration,
 	) -> Option<Box<[f32; T]>> {
-		let BufferSlice { values, rate } = self.take_next(interval);
+		let BufferSlice { channels, scope, rate } = self.take_next(interval);
+
+		let new_samples = channels.iter().map(Vec::len).min().unwrap_or(0);
+
+		if new_samples == 0 {
+			return None;
+		}
+
+		self.levels = Some(self.meter.measure(&channels, rate));
+
+		let history_size = self.config.fft_size.max(2);
+		let history_invalid = self.history.len() != channels.len()
+			|| self.history.iter().any(|ring| ring.capacity() != history_size);
+
+		if history_invalid {
+			self.history = channels.iter()
+				.map(|_| RingBuffer::new(history_size))
+				.collect();
+			self.unanalysed = 0;
+		}
+
+		for (ring, values) in self.history.iter_mut().zip(&channels) {
+			ring.extend(&values[..new_samples]);
+		}
+
+		if self.scope.len() != scope.len() {
+			self.scope = scope.iter()
+				.map(|_| RingBuffer::new(BUFFER_SIZE))
+				.collect();
+		}
+
+		for (ring, values) in self.scope.iter_mut().zip(&scope) {
+			ring.extend(values);
+		}
+
+		self.unanalysed += new_samples;
+
+		if self.unanalysed < self.config.hop {
+			return None;
+		}
 
-		if values.len() < 2 {
+		self.unanalysed = 0;
+
+		let channels = self.history.iter()
+			.map(RingBuffer::to_vec)
+			.collect::<Vec<_>>();
+
+		let length = channels.iter().map(Vec::len).min().unwrap_or(0);
+
+		if length < 2 {
 			return None;
 		}
 
-		let power_of_2 = f32::log2(values.len() as f32).floor() as u32;
+		let power_of_2 = f32::log2(length as f32).floor() as u32;
+		let bins_per_channel = T / channels.len();
+
+		let mut spectrum = Vec::with_capacity(T);
+
+		for values in &channels {
+			let magnitudes = match self.config.analysis {
+				Analysis::Fft => self.fft(values, power_of_2, rate, bins_per_channel)?,
+				Analysis::ConstantQ => self.constant_q(values, power_of_2, rate, bins_per_channel),
+			};
+
+			spectrum.extend(magnitudes.into_iter().map(|value| {
+				let log_scale = f32::log10(1.0 + value);
+
+				log_scale * self.config.scale
+			}));
+		}
+
+		spectrum.resize(T, 0.0);
+
+		Some(spectrum.into_boxed_slice().try_into().unwrap())
+	}
+
+	/// The most recent samples, with the first two channels side by side or
+	/// the only channel in both. They are taken before the channels are
+	/// mixed, so stay apart in any channel mode.
+	/// Padded with silence at the start until enough samples have arrived.
+	pub fn waveform(&self) -> Box<[[f32; 2]; BUFFER_SIZE]> {
+		let mut waveform = Box::new([[0.0; 2]; BUFFER_SIZE]);
+
+		let Some(left) = self.scope.first().map(|ring| ring.latest(BUFFER_SIZE)) else {
+			return waveform;
+		};
+
+		let right = self.scope.get(1).map_or_else(|| left.clone(), |ring| ring.latest(BUFFER_SIZE));
+
+		let length = usize::min(left.len(), right.len());
+		let samples = left[(left.len() - length)..].iter().zip(&right[(right.len() - length)..]);
+
+		for (pair, (left, right)) in waveform[(BUFFER_SIZE - length)..].iter_mut().zip(samples) {
+			*pair = [*left, *right];
+		}
+
+		waveform
+	}
+
+	/// The levels measured by the latest `fft_interval`, if it consumed any
+	/// samples since the levels were last taken.
+	pub fn take_levels(&mut self) -> Option<Levels> {
+		self.levels.take()
+	}
+
+	fn fft(
+		&mut self,
+		values: &[f32],
+		power_of_2: u32,
+		rate: f32,
+		bins: usize,
+	) -> Option<Vec<f32>> {
 		let size = 2_u32.pow(power_of_2) as usize;
 
 		let fft = self.ffts.entry(power_of_2 as u8).or_insert_with(|| {
@@ -109,7 +372,7 @@ impl BufferManager {
 			}
 		});
 
-		let mut truncated_data = values[0..size].iter()
+		let mut truncated_data = values[(values.len() - size)..].iter()
 			.cloned()
 			.zip(fft.window.iter())
 			.map(|(val, scale)| Complex { re: val * scale, im: 0.0 })
@@ -118,8 +381,8 @@ impl BufferManager {
 		fft.algorithm.process(truncated_data.as_mut_slice());
 
 		// NOTE: taking anything > rate/2 results in Hermitian symmetry
-		let max_frequency_ratio = CONFIG.ceiling_frequency / rate;
-		let min_frequency_ratio = CONFIG.floor_frequency / rate;
+		let max_frequency_ratio = self.config.ceiling_frequency / rate;
+		let min_frequency_ratio = self.config.floor_frequency / rate;
 		let max_index = usize::min(size, (size as f32 * max_frequency_ratio) as usize);
 		let min_index = (size as f32 * min_frequency_ratio) as usize;
 
@@ -140,24 +403,56 @@ impl BufferManager {
 
 		Some(Linear::builder()
 			.elements(&truncated_data[range])
-			.knots(power_range(CONFIG.power_scale_frequencies, count).as_ref())
+			.knots(power_range(self.config.power_scale_frequencies, count).as_ref())
 			//.equidistant::<f32>()
 			//.normalized()
 			.build()
 			.unwrap()
-			.take(T)
+			.take(bins)
 			.map(|Complex { re, im }| {
 				let power = f32::sqrt(re * re + im * im);
-				let value = power / fft.scaling_factor;
-				let log_scale = f32::log10(1.0 + value);
-				
-				log_scale * CONFIG.scale
+				power / fft.scaling_factor
 			})
-			.collect::<Box<_>>()
-			.try_into().unwrap())
+			.collect())
+	}
+
+	fn constant_q(
+		&mut self,
+		values: &[f32],
+		power_of_2: u32,
+		rate: f32,
+		bins: usize,
+	) -> Vec<f32> {
+		let size = 2_u32.pow(power_of_2) as usize;
+		// the rate is averaged across buffers so may not be exact
+		let rate = rate.round() as u32;
+
+		let transform = self.constant_qs
+			.entry((power_of_2 as u8, rate, bins))
+			.or_insert_with(|| {
+				ConstantQ::new(
+					size,
+					rate as f32,
+					bins,
+					self.config.floor_frequency,
+					self.config.ceiling_frequency,
+					self.config.bandwidth_offset,
+				)
+			});
+
+		transform.process(values).collect()
+	}
+
+	pub fn source_state(&self) -> SourceState {
+		self.source_state
 	}
 
-	pub fn fill_buffer(&mut self, buffer: &[f32], rate: u32) {
+	pub fn set_source_state(&mut self, state: SourceState) {
+		self.source_state = state;
+	}
+
+	/// Queues a block of audio with one slice per channel.
+	pub fn fill_buffer(&mut self, channels: &[&[f32]], rate: u32) {
 		if self.buffers.len() >= BUFFER_TARGET {
 			// render thread is behind (or not drawing)
 			// pause as to not waste resources copying data
@@ -167,7 +462,44 @@ impl BufferManager {
 		self.buffers.push_back(AudioBuffer {
 			position: 0,
 			rate: rate as f32,
-			data: Vec::from(buffer).into_boxed_slice(),
+			channels: self.config.channels.mix(channels),
+			scope: channels.iter()
+				.take(2)
+				.map(|c| Box::from(*c))
+				.collect(),
 		});
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn waveform_keeps_channels_apart_when_mixed() {
+		let mut manager = BufferManager::new(Config { channels: ChannelMode::Mono, ..Config::default() });
+
+		manager.fill_buffer(&[&[0.5; 480], &[-0.5; 480]], 48000);
+		manager.fft_interval::<BUFFER_SIZE>(Duration::from_millis(10));
+
+		assert_eq!(manager.waveform()[BUFFER_SIZE - 1], [0.5, -0.5]);
+	}
+
+	#[test]
+	fn analysis_waits_for_a_hop_of_new_samples() {
+		let mut manager = BufferManager::new(Config { fft_size: 1024, hop: 1000, ..Config::default() });
+
+		// each interval consumes a little under 480 samples
+		for _ in 0..2 {
+			manager.fill_buffer(&[&[0.0; 480]], 48000);
+			assert!(manager.fft_interval::<BUFFER_SIZE>(Duration::from_millis(10)).is_none());
+		}
+
+		assert!(manager.unanalysed >= 900);
+
+		manager.fill_buffer(&[&[0.0; 480]], 48000);
+		manager.fft_interval::<BUFFER_SIZE>(Duration::from_millis(10));
+
+		assert_eq!(manager.unanalysed, 0);
+	}
+}
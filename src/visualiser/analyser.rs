@@ -0,0 +1,63 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::config::Config;
+
+use super::{idle, AutoGain, BeatDetector, BufferManager, Frame, Smoothing};
+
+/// Turns the captured audio into frames, applying the gain, smoothing and
+/// level tracking in between.
+pub(crate) struct Analyser {
+	visualiser: Arc<RwLock<BufferManager>>,
+	auto_gain: AutoGain,
+	smoothing: Smoothing,
+	beat_detector: BeatDetector,
+	/// the latest analysis
+	frame: Frame,
+}
+
+impl Analyser {
+	pub fn new(visualiser: Arc<RwLock<BufferManager>>) -> Self {
+		Self {
+			visualiser,
+			auto_gain: AutoGain::default(),
+			smoothing: Smoothing::default(),
+			beat_detector: BeatDetector::default(),
+			frame: Frame::empty(),
+		}
+	}
+
+	pub fn frame(&self) -> &Frame {
+		&self.frame
+	}
+
+	pub fn set_config(&self, config: Config) {
+		self.visualiser.write().unwrap().set_config(config);
+	}
+
+	/// Analyses the audio covering `interval` since the previous frame, or
+	/// animates the idle spectrum if the source isn't streaming.
+	pub fn advance(&mut self, config: &Config, interval: Duration) {
+		let (mut spectrum, levels) = {
+			let mut visualiser = self.visualiser.write().unwrap();
+			let spectrum = visualiser.fft_interval(interval);
+
+			self.frame.waveform = visualiser.waveform();
+			self.frame.source_state = visualiser.source_state();
+
+			(spectrum, visualiser.take_levels())
+		};
+
+		self.auto_gain.update(config, spectrum.as_deref_mut(), interval);
+
+		// the smoothing eases between the audio and the animation
+		if let Some(idle) = idle::spectrum(self.frame.source_state, self.frame.time) {
+			spectrum = Some(idle);
+		}
+
+		self.smoothing.update(config, spectrum, interval, &mut self.frame);
+		self.frame.levels = self.beat_detector.update(levels, interval);
+		self.frame.time += interval.as_secs_f32();
+		self.frame.step += 1;
+	}
+}
@@ -0,0 +1,134 @@
+use rustfft::num_complex::Complex;
+
+/// The lowest frequency a constant-Q transform will analyse.
+/// Below this the windows get impractically long.
+const MINIMUM_FREQUENCY: f32 = 20.0;
+
+struct Kernel {
+	/// Index of the first sample the kernel applies to
+	start: usize,
+	coefficients: Box<[Complex<f32>]>,
+	scaling_factor: f32,
+}
+
+/// A transform with logarithmically spaced bins, each using a window long
+/// enough to resolve it from its neighbours.
+///
+/// Windows are aligned to the end of the analysed samples so every bin
+/// reflects the most recent audio.
+/// Windows which would be longer than the available samples are truncated,
+/// which lowers the Q for those bins.
+pub(crate) struct ConstantQ {
+	size: usize,
+	kernels: Box<[Kernel]>,
+}
+
+impl ConstantQ {
+	/// `bandwidth_offset` is added to the bandwidth (in Hz) of every bin.
+	/// Zero gives a true constant-Q transform while larger values shorten
+	/// the windows of the lowest bins (a variable-Q transform).
+	pub fn new(
+		size: usize,
+		rate: f32,
+		bins: usize,
+		floor: f32,
+		ceiling: f32,
+		bandwidth_offset: f32,
+	) -> Self {
+		let floor = floor.max(MINIMUM_FREQUENCY);
+		let ceiling = ceiling.min(rate / 2.0).max(floor);
+
+		let q = 1.0 / (f32::exp2(1.0 / bins_per_octave(bins, floor, ceiling)) - 1.0);
+
+		let kernels = centre_frequencies(bins, floor, ceiling)
+			.map(|frequency| {
+				let bandwidth = frequency / q + bandwidth_offset;
+				let length = ((rate / bandwidth).ceil() as usize).clamp(2, size);
+
+				let coefficients = apodize::hanning_iter(length)
+					.enumerate()
+					.map(|(n, window)| {
+						let phase = -std::f32::consts::TAU * frequency * n as f32 / rate;
+						Complex::from_polar(window as f32, phase)
+					})
+					.collect();
+
+				Kernel {
+					start: size - length,
+					coefficients,
+					scaling_factor: (length as f32).sqrt(),
+				}
+			})
+			.collect();
+
+		Self { size, kernels }
+	}
+
+	/// The magnitude of each bin for the last `size` values.
+	pub fn process<'a>(&'a self, values: &'a [f32]) -> impl Iterator<Item = f32> + 'a {
+		let values = &values[values.len() - self.size..];
+
+		self.kernels.iter().map(move |kernel| {
+			let sum = values[kernel.start..].iter()
+				.zip(kernel.coefficients.iter())
+				.map(|(&value, &coefficient)| coefficient * value)
+				.sum::<Complex<f32>>();
+
+			sum.norm() / kernel.scaling_factor
+		})
+	}
+}
+
+fn bins_per_octave(bins: usize, floor: f32, ceiling: f32) -> f32 {
+	let octaves = f32::log2(ceiling / floor);
+	(bins.max(2) - 1) as f32 / octaves.max(f32::EPSILON)
+}
+
+/// The frequency each of `bins` bins is centred on, spaced geometrically from
+/// `floor` to `ceiling`.
+fn centre_frequencies(bins: usize, floor: f32, ceiling: f32) -> impl Iterator<Item = f32> {
+	let bins_per_octave = bins_per_octave(bins, floor, ceiling);
+
+	(0..bins).map(move |bin| floor * f32::exp2(bin as f32 / bins_per_octave))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RATE: f32 = 48000.0;
+
+	#[test]
+	fn centre_frequencies_are_spaced_geometrically() {
+		let frequencies = centre_frequencies(12, 100.0, 6400.0).collect::<Vec<_>>();
+
+		assert!((frequencies[0] - 100.0).abs() < 1e-3);
+		assert!((frequencies[11] - 6400.0).abs() < 0.1, "ends at {}", frequencies[11]);
+
+		let ratio = frequencies[1] / frequencies[0];
+
+		for pair in frequencies.windows(2) {
+			assert!((pair[1] / pair[0] - ratio).abs() < 1e-4, "{:?} has a different ratio", pair);
+		}
+	}
+
+	#[test]
+	fn pure_tone_peaks_in_its_bin() {
+		let (bins, floor, ceiling) = (48, 50.0, 10000.0);
+		let transform = ConstantQ::new(4096, RATE, bins, floor, ceiling, 0.0);
+
+		for bin in [5, 20, 30, 45] {
+			let frequency = centre_frequencies(bins, floor, ceiling).nth(bin).unwrap();
+			let tone = (0..4096)
+				.map(|n| (std::f32::consts::TAU * frequency * n as f32 / RATE).sin())
+				.collect::<Vec<_>>();
+
+			let magnitudes = transform.process(&tone).collect::<Vec<_>>();
+			let peak = (0..bins)
+				.max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+				.unwrap();
+
+			assert_eq!(peak, bin, "{} Hz peaked in bin {}", frequency, peak);
+		}
+	}
+}
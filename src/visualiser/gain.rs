@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use crate::BUFFER_SIZE;
+use crate::config::Config;
+
+use super::smoothing::approach;
+
+/// How many times faster the gain falls than it rises, so a sudden loud
+/// passage only overshoots briefly.
+const FALL_SPEEDUP: f32 = 8.0;
+
+/// Scales the spectrum so the chosen percentile of its values sits at the
+/// target level.
+///
+/// The gain follows the level slowly, so the relative loudness within a track
+/// is kept. Levels below the noise floor are treated as silence, during which
+/// the gain falls back towards 1 rather than amplifying the noise.
+pub(crate) struct AutoGain {
+	gain: f32,
+	/// level of the most recent spectrum before the gain was applied
+	level: f32,
+}
+
+impl Default for AutoGain {
+	fn default() -> Self {
+		Self { gain: 1.0, level: 0.0 }
+	}
+}
+
+impl AutoGain {
+	/// Advances by `elapsed` and applies the gain to `spectrum` if there is
+	/// one. Does nothing unless automatic gain is enabled.
+	pub fn update(
+		&mut self,
+		config: &Config,
+		spectrum: Option<&mut [f32; BUFFER_SIZE]>,
+		elapsed: Duration,
+	) {
+		if !config.auto_gain {
+			return;
+		}
+
+		if let Some(spectrum) = spectrum.as_deref() {
+			self.level = percentile(spectrum, config.gain_percentile);
+		}
+
+		let min_gain = config.min_gain.max(f32::EPSILON);
+		let max_gain = config.max_gain.max(min_gain);
+		let speed = config.gain_speed / 1000.0;
+
+		let (target, speed) = if self.level > config.noise_floor {
+			let target = (config.gain_target / self.level).clamp(min_gain, max_gain);
+			(target, if target < self.gain { speed / FALL_SPEEDUP } else { speed })
+		} else {
+			// drift back to unity so silence isn't amplified, slowly enough
+			// that a short pause doesn't lose the gain
+			(1.0f32.clamp(min_gain, max_gain), speed)
+		};
+
+		// adapt in the log domain so doubling and halving take equally long
+		self.gain = approach(self.gain.ln(), target.ln(), elapsed.as_secs_f32(), speed).exp();
+
+		if let Some(spectrum) = spectrum {
+			spectrum.iter_mut().for_each(|value| *value *= self.gain);
+		}
+	}
+}
+
+fn percentile(values: &[f32], percentile: f32) -> f32 {
+	let mut values = values.to_vec();
+	let index = ((values.len() - 1) as f32 * percentile.clamp(0.0, 1.0)).round() as usize;
+
+	*values.select_nth_unstable_by(index, f32::total_cmp).1
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config() -> Config {
+		Config { auto_gain: true, ..Config::default() }
+	}
+
+	#[test]
+	fn quiet_audio_is_amplified() {
+		let config = config();
+		let mut gain = AutoGain::default();
+		let mut spectrum = [0.1; BUFFER_SIZE];
+
+		gain.update(&config, Some(&mut spectrum), Duration::from_secs(60));
+
+		assert!((spectrum[0] - config.gain_target).abs() < 1e-3, "{}", spectrum[0]);
+	}
+
+	#[test]
+	fn silence_returns_to_unity() {
+		let config = config();
+		let mut gain = AutoGain { gain: 5.0, level: 0.0 };
+		let mut spectrum = [0.0; BUFFER_SIZE];
+
+		gain.update(&config, Some(&mut spectrum), Duration::from_millis(100));
+		assert!(gain.gain < 5.0 && gain.gain > 1.0, "{}", gain.gain);
+
+		gain.update(&config, Some(&mut spectrum), Duration::from_secs(60));
+		assert!((gain.gain - 1.0).abs() < 1e-3, "{}", gain.gain);
+	}
+}
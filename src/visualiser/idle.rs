@@ -0,0 +1,33 @@
+use std::f32::consts::TAU;
+
+use crate::BUFFER_SIZE;
+use crate::audio::SourceState;
+
+/// Height of the idle animation, low enough to tell apart from quiet audio.
+const HEIGHT: f32 = 0.08;
+
+/// A spectrum to show instead of the audio while the source isn't streaming,
+/// so it is clear the visualiser is still running. Each state moves
+/// differently: a slow wave when idle, a quicker sweep while connecting and
+/// the whole spectrum breathing while disconnected.
+pub fn spectrum(state: SourceState, time: f32) -> Option<Box<[f32; BUFFER_SIZE]>> {
+	let level: fn(f32, f32) -> f32 = match state {
+		SourceState::Streaming => return None,
+		SourceState::Idle => |position, time| wave(position * 2.0 - time * 0.25),
+		SourceState::Connecting => |position, time| wave(position - time).powi(4),
+		SourceState::Disconnected => |_, time| wave(time * 0.5),
+	};
+
+	let mut spectrum = Box::new([0.0; BUFFER_SIZE]);
+
+	for (i, value) in spectrum.iter_mut().enumerate() {
+		*value = HEIGHT * level(i as f32 / BUFFER_SIZE as f32, time);
+	}
+
+	Some(spectrum)
+}
+
+/// A sine wave from 0 to 1 with a period of 1.
+fn wave(phase: f32) -> f32 {
+	0.5 - 0.5 * f32::cos(TAU * phase)
+}
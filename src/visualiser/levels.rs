@@ -0,0 +1,253 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use super::smoothing::approach;
+
+/// Upper edge of the bass band in hertz.
+const BASS_CUTOFF: f32 = 250.0;
+/// Upper edge of the mid band in hertz, above which is treble.
+const TREBLE_CUTOFF: f32 = 4000.0;
+
+/// How far the bass has to rise above its recent average to count as a beat.
+const BEAT_THRESHOLD: f32 = 1.5;
+/// Time constant of the average beats are compared against, in seconds.
+const BEAT_AVERAGE_TIME: f32 = 1.0;
+/// Shortest time between beats in seconds, so one kick is not counted twice.
+const MIN_BEAT_INTERVAL: f32 = 0.15;
+/// Bass below this is too quiet to hold a beat.
+const BEAT_FLOOR: f32 = 0.01;
+
+/// Loudness of the audio before any scaling or gain, as a whole and in broad
+/// frequency bands.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Levels {
+	pub rms: f32,
+	pub peak: f32,
+	/// RMS below `BASS_CUTOFF`
+	pub bass: f32,
+	/// RMS between `BASS_CUTOFF` and `TREBLE_CUTOFF`
+	pub mid: f32,
+	/// RMS above `TREBLE_CUTOFF`
+	pub treble: f32,
+	/// whether a beat started since the previous frame
+	pub beat: bool,
+}
+
+/// Measures the levels of the samples as they are consumed.
+///
+/// The bands are split by one pole filters rather than taken from the
+/// spectrum, so they are the same whichever analysis is used.
+#[derive(Default)]
+pub(crate) struct LevelMeter {
+	bass_filter: f32,
+	treble_filter: f32,
+}
+
+impl LevelMeter {
+	/// The levels of `channels` mixed together.
+	pub fn measure(&mut self, channels: &[Vec<f32>], rate: f32) -> Levels {
+		let length = channels.iter().map(Vec::len).min().unwrap_or(0);
+
+		if length == 0 {
+			return Levels::default();
+		}
+
+		let coefficient = |cutoff: f32| 1.0 - f32::exp(-TAU * cutoff / rate);
+		let bass_coefficient = coefficient(BASS_CUTOFF);
+		let treble_coefficient = coefficient(TREBLE_CUTOFF);
+
+		let scale = 1.0 / channels.len() as f32;
+
+		let mut levels = Levels::default();
+
+		for i in 0..length {
+			let sample = channels.iter().map(|channel| channel[i]).sum::<f32>() * scale;
+
+			self.bass_filter += (sample - self.bass_filter) * bass_coefficient;
+			self.treble_filter += (sample - self.treble_filter) * treble_coefficient;
+
+			let bass = self.bass_filter;
+			let mid = self.treble_filter - self.bass_filter;
+			let treble = sample - self.treble_filter;
+
+			levels.rms += sample * sample;
+			levels.peak = levels.peak.max(sample.abs());
+			levels.bass += bass * bass;
+			levels.mid += mid * mid;
+			levels.treble += treble * treble;
+		}
+
+		let mean_root = |sum: f32| (sum / length as f32).sqrt();
+
+		Levels {
+			rms: mean_root(levels.rms),
+			bass: mean_root(levels.bass),
+			mid: mean_root(levels.mid),
+			treble: mean_root(levels.treble),
+			..levels
+		}
+	}
+}
+
+/// Flags beats as the bass rising well above its recent average.
+#[derive(Default)]
+pub(crate) struct BeatDetector {
+	levels: Levels,
+	average: f32,
+	/// seconds since the last beat
+	since_beat: f32,
+}
+
+impl BeatDetector {
+	/// Advances by `elapsed`, looking for a beat in the newly measured levels
+	/// if there are any. Returns the latest levels.
+	pub fn update(&mut self, measured: Option<Levels>, elapsed: Duration) -> Levels {
+		let elapsed = elapsed.as_secs_f32();
+		self.since_beat += elapsed;
+
+		let Some(levels) = measured else {
+			return Levels { beat: false, ..self.levels };
+		};
+
+		let beat = levels.bass > BEAT_FLOOR
+			&& levels.bass > self.average * BEAT_THRESHOLD
+			&& self.since_beat >= MIN_BEAT_INTERVAL;
+
+		if beat {
+			self.since_beat = 0.0;
+		}
+
+		self.average = approach(self.average, levels.bass, elapsed, BEAT_AVERAGE_TIME);
+		self.levels = Levels { beat, ..levels };
+
+		self.levels
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RATE: f32 = 48000.0;
+	/// samples in a frame drawn at 60Hz
+	const FRAME: usize = 800;
+	const ELAPSED: Duration = Duration::from_micros(16_667);
+
+	fn tone(frequency: f32, length: usize) -> Vec<f32> {
+		(0..length)
+			.map(|i| (TAU * frequency * i as f32 / RATE).sin())
+			.collect()
+	}
+
+	/// The levels of a second of `frequency`, after the filters have settled.
+	fn band_levels(frequency: f32) -> Levels {
+		let mut meter = LevelMeter::default();
+		let samples = tone(frequency, 2 * RATE as usize);
+		let (settling, measured) = samples.split_at(RATE as usize);
+
+		meter.measure(&[settling.to_vec()], RATE);
+		meter.measure(&[measured.to_vec()], RATE)
+	}
+
+	/// Which frames of `signal` flag a beat when it is measured a frame at a
+	/// time.
+	fn beats(signal: &[f32]) -> Vec<usize> {
+		let mut meter = LevelMeter::default();
+		let mut detector = BeatDetector::default();
+
+		signal.chunks(FRAME)
+			.enumerate()
+			.filter(|(_, frame)| {
+				let levels = meter.measure(&[frame.to_vec()], RATE);
+				detector.update(Some(levels), ELAPSED).beat
+			})
+			.map(|(i, _)| i)
+			.collect()
+	}
+
+	/// Clicks of `width` samples at the end of every `period` frames, so the
+	/// first is after `MIN_BEAT_INTERVAL` when `period` is long enough.
+	fn clicks(period: usize, width: usize, frames: usize) -> Vec<f32> {
+		(0..frames * FRAME)
+			.map(|i| if (i + FRAME) % (period * FRAME) < width { 1.0 } else { 0.0 })
+			.collect()
+	}
+
+	#[test]
+	fn tones_land_in_their_own_band() {
+		let bass = band_levels(60.0);
+		assert!(bass.bass > 2.0 * bass.mid.max(bass.treble), "{:?}", bass);
+
+		let mid = band_levels(1000.0);
+		assert!(mid.mid > 2.0 * mid.bass.max(mid.treble), "{:?}", mid);
+
+		let treble = band_levels(12000.0);
+		assert!(treble.treble > 2.0 * treble.bass.max(treble.mid), "{:?}", treble);
+	}
+
+	#[test]
+	fn whole_signal_levels_ignore_the_bands() {
+		let levels = band_levels(1000.0);
+
+		assert!((levels.rms - f32::sqrt(0.5)).abs() < 0.01, "{:?}", levels);
+		assert!((levels.peak - 1.0).abs() < 0.01, "{:?}", levels);
+	}
+
+	#[test]
+	fn channels_are_mixed_before_measuring() {
+		let mut meter = LevelMeter::default();
+		let left = tone(60.0, FRAME);
+		let right = left.iter().map(|sample| -sample).collect();
+
+		assert_eq!(meter.measure(&[left, right], RATE), Levels::default());
+	}
+
+	#[test]
+	fn every_click_is_a_beat() {
+		// twice a second for four seconds
+		let signal = clicks(30, 240, 240);
+		assert_eq!(beats(&signal), (29..240).step_by(30).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn clicks_too_close_together_are_one_beat() {
+		// every other frame is closer than MIN_BEAT_INTERVAL
+		let signal = clicks(2, 240, 20);
+		let beats = beats(&signal);
+
+		assert!(beats.len() < 10, "{:?}", beats);
+		assert!(beats.windows(2).all(|pair| (pair[1] - pair[0]) as f32 * ELAPSED.as_secs_f32() >= MIN_BEAT_INTERVAL));
+	}
+
+	#[test]
+	fn steady_tones_stop_being_beats_once_the_average_catches_up() {
+		// the average reaches the level over BEAT_THRESHOLD after about a
+		// second and a tenth
+		let caught_up = (1.2 / ELAPSED.as_secs_f32()) as usize;
+
+		for frequency in [60.0, 12000.0] {
+			let beats = beats(&tone(frequency, 3 * caught_up * FRAME));
+			assert!(beats.iter().all(|&beat| beat < caught_up), "{}Hz: {:?}", frequency, beats);
+		}
+	}
+
+	#[test]
+	fn silence_is_not_a_beat() {
+		assert!(beats(&vec![0.0; 60 * FRAME]).is_empty());
+	}
+
+	#[test]
+	fn beats_are_only_flagged_once() {
+		let mut meter = LevelMeter::default();
+		let mut detector = BeatDetector::default();
+		let levels = meter.measure(&[clicks(1, 240, 1)], RATE);
+
+		// nothing counts as a beat until MIN_BEAT_INTERVAL has passed
+		assert!(!detector.update(Some(levels), ELAPSED).beat);
+		detector.update(None, Duration::from_secs(1));
+
+		assert!(detector.update(Some(levels), ELAPSED).beat);
+		// frames without new samples keep the levels but not the beat
+		assert_eq!(detector.update(None, ELAPSED), Levels { beat: false, ..levels });
+	}
+}
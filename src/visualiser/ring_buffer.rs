@@ -0,0 +1,94 @@
+/// A fixed size history of samples where new values overwrite the oldest.
+pub(crate) struct RingBuffer {
+	data: Box<[f32]>,
+	/// Index the next value will be written to
+	position: usize,
+	len: usize,
+}
+
+impl RingBuffer {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			data: vec![0.0; capacity].into_boxed_slice(),
+			position: 0,
+			len: 0,
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.data.len()
+	}
+
+	pub fn extend(&mut self, values: &[f32]) {
+		let capacity = self.capacity();
+
+		// anything before the last `capacity` values would be overwritten anyway
+		let values = &values[values.len().saturating_sub(capacity)..];
+
+		let (head, tail) = values.split_at(usize::min(values.len(), capacity - self.position));
+		self.data[self.position..(self.position + head.len())].copy_from_slice(head);
+		self.data[..tail.len()].copy_from_slice(tail);
+
+		self.position = (self.position + values.len()) % capacity;
+		self.len = usize::min(capacity, self.len + values.len());
+	}
+
+	/// The stored values from oldest to newest.
+	pub fn to_vec(&self) -> Vec<f32> {
+		self.latest(self.len)
+	}
+
+	/// The newest `count` values, or all of them if there are fewer, from
+	/// oldest to newest.
+	pub fn latest(&self, count: usize) -> Vec<f32> {
+		let count = usize::min(count, self.len);
+		let start = (self.position + self.capacity() - count) % self.capacity();
+
+		if start + count <= self.capacity() {
+			self.data[start..(start + count)].to_vec()
+		} else {
+			[&self.data[start..], &self.data[..self.position]].concat()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wraps_around_in_order() {
+		let mut ring = RingBuffer::new(4);
+
+		ring.extend(&[1.0, 2.0, 3.0]);
+		assert_eq!(ring.to_vec(), [1.0, 2.0, 3.0]);
+
+		ring.extend(&[4.0, 5.0]);
+		assert_eq!(ring.to_vec(), [2.0, 3.0, 4.0, 5.0]);
+
+		ring.extend(&[6.0]);
+		assert_eq!(ring.to_vec(), [3.0, 4.0, 5.0, 6.0]);
+		assert_eq!(ring.latest(2), [5.0, 6.0]);
+	}
+
+	#[test]
+	fn keeps_the_end_of_pushes_larger_than_the_capacity() {
+		let mut ring = RingBuffer::new(3);
+		ring.extend(&[1.0]);
+
+		ring.extend(&[2.0, 3.0, 4.0, 5.0, 6.0]);
+		assert_eq!(ring.to_vec(), [4.0, 5.0, 6.0]);
+
+		ring.extend(&[7.0, 8.0, 9.0]);
+		assert_eq!(ring.to_vec(), [7.0, 8.0, 9.0]);
+	}
+
+	#[test]
+	fn latest_is_limited_to_what_is_stored() {
+		let mut ring = RingBuffer::new(8);
+		ring.extend(&[1.0, 2.0]);
+
+		assert_eq!(ring.latest(5), [1.0, 2.0]);
+		assert!(RingBuffer::new(8).to_vec().is_empty());
+	}
+}
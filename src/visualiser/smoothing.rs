@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use crate::BUFFER_SIZE;
+use crate::config::Config;
+
+use super::Frame;
+
+/// Moves `value` towards `target` as a first order low-pass filter would.
+pub(super) fn approach(value: f32, target: f32, elapsed: f32, time_constant: f32) -> f32 {
+	if time_constant <= 0.0 {
+		target
+	} else {
+		value + (target - value) * (1.0 - f32::exp(-elapsed / time_constant))
+	}
+}
+
+/// Smooths the spectrum over time and tracks the recent peak of each value.
+///
+/// All rates are in terms of real time so the result does not depend on how
+/// often frames are drawn.
+pub(crate) struct Smoothing {
+	target: Box<[f32; BUFFER_SIZE]>,
+	values: Box<[f32; BUFFER_SIZE]>,
+	/// speed at which each value is falling when gravity is enabled
+	velocities: Box<[f32; BUFFER_SIZE]>,
+	peaks: Box<[f32; BUFFER_SIZE]>,
+	/// how long each peak has been held, in seconds
+	peak_ages: Box<[f32; BUFFER_SIZE]>,
+	peak_velocities: Box<[f32; BUFFER_SIZE]>,
+}
+
+impl Default for Smoothing {
+	fn default() -> Self {
+		Self {
+			target: Box::new([0.0; BUFFER_SIZE]),
+			values: Box::new([0.0; BUFFER_SIZE]),
+			velocities: Box::new([0.0; BUFFER_SIZE]),
+			peaks: Box::new([0.0; BUFFER_SIZE]),
+			peak_ages: Box::new([0.0; BUFFER_SIZE]),
+			peak_velocities: Box::new([0.0; BUFFER_SIZE]),
+		}
+	}
+}
+
+impl Smoothing {
+	/// Advances by `elapsed`, moving towards `spectrum` if there is one or the
+	/// previous spectrum otherwise, and writes the result to `frame`.
+	pub fn update(
+		&mut self,
+		config: &Config,
+		spectrum: Option<Box<[f32; BUFFER_SIZE]>>,
+		elapsed: Duration,
+		frame: &mut Frame,
+	) {
+		if let Some(spectrum) = spectrum {
+			self.target = spectrum;
+		}
+
+		let elapsed = elapsed.as_secs_f32();
+		let attack = config.attack / 1000.0;
+		let decay = config.decay / 1000.0;
+		let peak_hold = config.peak_hold / 1000.0;
+
+		for i in 0..BUFFER_SIZE {
+			let target = self.target[i];
+			let value = &mut self.values[i];
+			let velocity = &mut self.velocities[i];
+
+			if target >= *value {
+				*value = approach(*value, target, elapsed, attack);
+				*velocity = 0.0;
+			} else if config.gravity > 0.0 {
+				*velocity += config.gravity * elapsed;
+				*value = f32::max(target, *value - *velocity * elapsed);
+			} else {
+				*value = approach(*value, target, elapsed, decay);
+			}
+
+			let peak = &mut self.peaks[i];
+			let peak_age = &mut self.peak_ages[i];
+			let peak_velocity = &mut self.peak_velocities[i];
+
+			if peak_hold <= 0.0 {
+				*peak = 0.0;
+			} else if *value >= *peak {
+				*peak = *value;
+				*peak_age = 0.0;
+				*peak_velocity = 0.0;
+			} else if *peak_age < peak_hold {
+				*peak_age += elapsed;
+			} else {
+				*peak_velocity += config.peak_gravity * elapsed;
+				*peak = f32::max(*value, *peak - *peak_velocity * elapsed);
+			}
+		}
+
+		*frame.spectrum = *self.values;
+		*frame.peaks = *self.peaks;
+		frame.delta = elapsed;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const STEP: Duration = Duration::from_millis(100);
+
+	fn constant(value: f32) -> Option<Box<[f32; BUFFER_SIZE]>> {
+		Some(Box::new([value; BUFFER_SIZE]))
+	}
+
+	fn assert_close(actual: f32, expected: f32) {
+		assert!((actual - expected).abs() < 1e-5, "{} is not {}", actual, expected);
+	}
+
+	fn config(attack: f32, decay: f32) -> Config {
+		Config {
+			attack,
+			decay,
+			gravity: 0.0,
+			peak_hold: 0.0,
+			..Config::default()
+		}
+	}
+
+	#[test]
+	fn rises_over_the_attack_time() {
+		let config = config(100.0, 0.0);
+		let mut smoothing = Smoothing::default();
+		let mut frame = Frame::empty();
+
+		// after one time constant a first order filter is 1 - 1/e of the way
+		smoothing.update(&config, constant(1.0), STEP, &mut frame);
+		assert_close(frame.spectrum[0], 1.0 - f32::exp(-1.0));
+
+		smoothing.update(&config, None, STEP, &mut frame);
+		assert_close(frame.spectrum[0], 1.0 - f32::exp(-2.0));
+	}
+
+	#[test]
+	fn falls_over_the_decay_time() {
+		let config = config(0.0, 200.0);
+		let mut smoothing = Smoothing::default();
+		let mut frame = Frame::empty();
+
+		smoothing.update(&config, constant(1.0), STEP, &mut frame);
+		assert_close(frame.spectrum[0], 1.0);
+
+		smoothing.update(&config, constant(0.0), STEP * 2, &mut frame);
+		assert_close(frame.spectrum[0], f32::exp(-1.0));
+	}
+
+	#[test]
+	fn step_size_does_not_change_the_result() {
+		let config = config(50.0, 0.0);
+		let mut coarse = Smoothing::default();
+		let mut fine = Smoothing::default();
+		let mut coarse_frame = Frame::empty();
+		let mut fine_frame = Frame::empty();
+
+		coarse.update(&config, constant(1.0), STEP, &mut coarse_frame);
+
+		fine.update(&config, constant(1.0), STEP / 4, &mut fine_frame);
+		for _ in 0..3 {
+			fine.update(&config, None, STEP / 4, &mut fine_frame);
+		}
+
+		assert_close(coarse_frame.spectrum[0], fine_frame.spectrum[0]);
+	}
+
+	#[test]
+	fn gravity_accelerates_the_fall() {
+		let config = Config { gravity: 2.0, ..config(0.0, 0.0) };
+		let mut smoothing = Smoothing::default();
+		let mut frame = Frame::empty();
+
+		smoothing.update(&config, constant(1.0), STEP, &mut frame);
+
+		smoothing.update(&config, constant(0.0), STEP, &mut frame);
+		assert_close(frame.spectrum[0], 1.0 - 0.2 * 0.1);
+
+		smoothing.update(&config, None, STEP, &mut frame);
+		assert_close(frame.spectrum[0], 0.98 - 0.4 * 0.1);
+	}
+
+	#[test]
+	fn peaks_hold_then_fall() {
+		let config = Config { peak_hold: 500.0, peak_gravity: 10.0, ..config(0.0, 0.0) };
+		let mut smoothing = Smoothing::default();
+		let mut frame = Frame::empty();
+
+		smoothing.update(&config, constant(1.0), STEP, &mut frame);
+		assert_close(frame.peaks[0], 1.0);
+
+		smoothing.update(&config, constant(0.0), STEP, &mut frame);
+		assert_close(frame.spectrum[0], 0.0);
+
+		for _ in 0..3 {
+			smoothing.update(&config, None, STEP, &mut frame);
+		}
+
+		assert_close(frame.peaks[0], 1.0);
+
+		let mut previous = frame.peaks[0];
+		let mut previous_drop = 0.0;
+
+		// once the hold is over each step falls further than the last
+		for _ in 0..4 {
+			smoothing.update(&config, None, STEP, &mut frame);
+
+			let drop = previous - frame.peaks[0];
+			assert!(drop >= previous_drop, "fell {} after {}", drop, previous_drop);
+
+			previous = frame.peaks[0];
+			previous_drop = drop;
+		}
+
+		assert!(frame.peaks[0] < 1.0);
+
+		for _ in 0..10 {
+			smoothing.update(&config, None, STEP, &mut frame);
+		}
+
+		assert_close(frame.peaks[0], 0.0);
+	}
+
+	#[test]
+	fn no_peaks_without_a_hold() {
+		let mut smoothing = Smoothing::default();
+		let mut frame = Frame::empty();
+
+		smoothing.update(&config(0.0, 0.0), constant(1.0), STEP, &mut frame);
+		assert_close(frame.peaks[0], 0.0);
+	}
+}
@@ -1,41 +1,91 @@
-use std::sync::{RwLock, Arc};
-use std::time::Duration;
+use std::sync::{RwLock, Arc, mpsc::{self, Receiver}};
+use std::time::{Duration, Instant};
 
+use notify::RecommendedWatcher;
 use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, EventQueue};
+use wayland_client::backend::ObjectId;
 use wayland_client::protocol::wl_surface::{self, WlSurface};
 use wayland_client::protocol::wl_registry::{self, WlRegistry};
 use wayland_client::protocol::wl_compositor::{self, WlCompositor};
 use wayland_client::protocol::wl_callback::{self, WlCallback};
 use wayland_client::protocol::wl_display::WlDisplay;
+use wayland_client::protocol::wl_output::{self, WlOutput};
 use wayland_client::protocol::wl_region::{self, WlRegion};
 use wayland_protocols::xdg::shell::client::xdg_surface::{self, XdgSurface};
 use wayland_protocols::xdg::shell::client::xdg_toplevel::{self, XdgToplevel};
 use wayland_protocols::xdg::shell::client::xdg_wm_base::{self, XdgWmBase};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::{self, ZwlrLayerShellV1};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1, Anchor, KeyboardInteractivity};
 
-use crate::BUFFER_SIZE;
-use crate::graphics::Graphics;
-use crate::visualiser::BufferManager;
+use crate::config::Config;
+use crate::graphics::{shaders, Graphics, GraphicsContext, ShaderCode, ShaderError};
+use crate::layout::{self, Shape};
+use crate::visualiser::{Analyser, BufferManager};
 
-struct GraphicsState {
-	surface: XdgSurface,
-	toplevel: XdgToplevel,
-	graphics: Graphics,
+use self::view::{Role, View};
+
+pub(crate) use self::layer_shell::{Edge, Layer};
+
+mod layer_shell;
+mod view;
+
+/// The highest version of `wl_output` used, which added output names.
+const OUTPUT_VERSION: u32 = 4;
+/// The highest version of `zwlr_layer_shell_v1` used, later versions only add
+/// requests which aren't needed.
+const LAYER_SHELL_VERSION: u32 = 1;
+/// Most times a layer closed by the compositor is opened again in quick
+/// succession before giving up on its output.
+const MAX_REOPENS: u32 = 3;
+/// Closes further apart than this start the count of reopens again.
+const REOPEN_RESET: Duration = Duration::from_secs(10);
+
+struct Output {
+	/// name of the global, used to tell when it is removed
+	global: u32,
+	output: WlOutput,
+	/// only known once the compositor has sent it
+	name: Option<String>,
+	/// times the view on this output was closed by the compositor and
+	/// opened again, each within `REOPEN_RESET` of the last
+	reopens: u32,
+	last_closed: Option<Instant>,
 }
 
 pub(crate) struct Window {
 	running: bool,
 	display: WlDisplay,
 	event_queue: Option<EventQueue<Self>>,
+	compositor: Option<WlCompositor>,
 	wm_base: Option<XdgWmBase>,
-	base_surface: Option<WlSurface>,
-	graphics_state: Option<GraphicsState>,
-	configured: bool,
-	visualiser: Arc<RwLock<BufferManager>>,
-	last_frame: u32,
+	layer_shell: Option<ZwlrLayerShellV1>,
+	outputs: Vec<Output>,
+	graphics_context: GraphicsContext,
+	/// the layer views are shown on, or `None` for a single window
+	layer: Option<Layer>,
+	/// whether the initial views have been created, after which views are
+	/// added as outputs appear
+	initialised: bool,
+	views: Vec<View>,
+	analyser: Analyser,
+	config: Config,
+	config_updates: Receiver<Config>,
+	shaders: ShaderCode,
+	/// stops watching the shader files when dropped
+	_shader_watcher: Option<RecommendedWatcher>,
+	/// shaders loaded after a change, compiled off the render thread
+	shader_updates: Receiver<Result<ShaderCode, ShaderError>>,
+	/// time of the latest analysis from the frame callbacks, in milliseconds
+	last_frame: Option<u32>,
 }
 
 impl Window {
-	pub fn new(visualiser: Arc<RwLock<BufferManager>>) -> Self {
+	pub fn new(
+		visualiser: Arc<RwLock<BufferManager>>,
+		config: Config,
+		config_updates: Receiver<Config>,
+		shaders: ShaderCode,
+	) -> Self {
 		let connection = Connection::connect_to_env().unwrap();
 	
 		let event_queue = connection.new_event_queue();
@@ -44,17 +94,29 @@ impl Window {
 		let display = connection.display();
 		display.get_registry(&queue_handle, ());
 	
-		Window {
+		let mut window = Window {
 			running: false,
 			display,
 			event_queue: Some(event_queue),
+			compositor: None,
 			wm_base: None,
-			base_surface: None,
-			graphics_state: None,
-			configured: false,
-			visualiser,
-			last_frame: 0,
-		}
+			layer_shell: None,
+			outputs: Vec::new(),
+			graphics_context: GraphicsContext::new(),
+			layer: None,
+			initialised: false,
+			views: Vec::new(),
+			analyser: Analyser::new(visualiser),
+			config,
+			config_updates,
+			shaders,
+			_shader_watcher: None,
+			shader_updates: mpsc::channel().1,
+			last_frame: None,
+		};
+
+		window.watch_shaders(false);
+		window
 	}
 
 	pub fn run(&mut self) {
@@ -62,31 +124,306 @@ impl Window {
 
 		let mut event_queue = self.event_queue.take().unwrap();
 
+		// the first roundtrip announces the globals and the second the
+		// details of the outputs bound in response
+		event_queue.roundtrip(self).unwrap();
+		event_queue.roundtrip(self).unwrap();
+
+		self.init_views(&event_queue.handle());
+
 		while self.running {
 			event_queue.blocking_dispatch(self).unwrap();
 		}
 	}
 
-	fn init_xdg_surface(&mut self, queue_handle: &QueueHandle<Window>) {
-		let wm_base = self.wm_base.as_ref().unwrap();
-		let base_surface = self.base_surface.as_ref().unwrap();
+	/// Applies the most recent config sent since the last frame.
+	fn update_config(&mut self) {
+		let Some(config) = self.config_updates.try_iter().last() else {
+			return;
+		};
 
-		let xdg_surface = wm_base.get_xdg_surface(base_surface, queue_handle, ());
-		let toplevel = xdg_surface.get_toplevel(queue_handle, ());
-		toplevel.set_title("Visualiser".into());
-		toplevel.set_app_id(env!("CARGO_PKG_NAME").into());
-		
-		let graphics = Graphics::new(&self.display, base_surface, [600, 400]);
+		for view in &mut self.views {
+			view.graphics.set_config(&config);
+
+			let output_name = view.output_name.as_deref();
+
+			if layout::unchanged(&self.config, &config, output_name) {
+				continue;
+			}
+
+			match layout::from_config(&config, output_name) {
+				Ok(layout) => view.graphics.set_layout(&layout),
+				Err(error) => eprintln!("Failed to load layout, keeping the previous one: {}", error),
+			}
+		}
+
+		let shaders_moved = config.vertex_shader != self.config.vertex_shader
+			|| config.fragment_shader != self.config.fragment_shader;
+
+		self.analyser.set_config(config.clone());
+		self.config = config;
+
+		if shaders_moved {
+			self.watch_shaders(true);
+		}
+	}
+
+	/// Starts watching the shader files in the config, replacing any previous
+	/// watcher, and loads them straight away if `load_now`.
+	///
+	/// Compiling can take a while, so it happens on the watcher's thread (or
+	/// a new one) and the results are picked up between frames.
+	fn watch_shaders(&mut self, load_now: bool) {
+		let (sender, receiver) = mpsc::channel();
+		self.shader_updates = receiver;
+
+		let load = {
+			let config = self.config.clone();
+			move || { let _ = sender.send(ShaderCode::load(&config)); }
+		};
+
+		if load_now {
+			std::thread::spawn(load.clone());
+		}
+
+		self._shader_watcher = shaders::watch(&self.config, load)
+			.unwrap_or_else(|error| {
+				eprintln!("Not watching the shaders for changes: {}", error);
+				None
+			});
+	}
+
+	/// Switches every view to newly loaded shaders, or none of them if they
+	/// failed to compile or any view can't build a pipeline from them.
+	fn set_shaders(&mut self, shaders: Result<ShaderCode, ShaderError>) {
+		let shaders = match shaders {
+			Ok(shaders) => shaders,
+			Err(error) => return eprintln!("Failed to load shaders, keeping the previous ones: {}", error),
+		};
+
+		let prepared = self.views.iter()
+			.map(|view| view.graphics.prepare_shaders(&shaders))
+			.collect::<Result<Vec<_>, _>>();
+
+		let prepared = match prepared {
+			Ok(prepared) => prepared,
+			Err(error) => return eprintln!("Failed to build pipeline, keeping the previous shaders: {}", error),
+		};
+
+		for (view, prepared) in self.views.iter_mut().zip(prepared) {
+			view.graphics.set_shaders(prepared);
+		}
+
+		self.shaders = shaders;
+	}
+
+	/// Analyses the audio up to `time`.
+	/// Views drawn for the same time, or an earlier one, share the analysis.
+	fn advance(&mut self, time: u32) {
+		let Some(last_frame) = self.last_frame.replace(time) else {
+			return;
+		};
+
+		let elapsed = time.wrapping_sub(last_frame);
+
+		// outputs with different refresh rates can report earlier times
+		if elapsed == 0 || elapsed > u32::MAX / 2 {
+			self.last_frame = Some(last_frame);
+			return;
+		}
+
+		let interval = Duration::from_millis(elapsed as u64);
+
+		self.update_config();
+
+		// saving can produce several events for the same change
+		if let Some(shaders) = self.shader_updates.try_iter().last() {
+			self.set_shaders(shaders);
+		}
+
+		self.analyser.advance(&self.config, interval);
+	}
+
+	fn view_mut(&mut self, surface: &ObjectId) -> Option<&mut View> {
+		self.views.iter_mut().find(|view| &view.surface.id() == surface)
+	}
+
+	fn remove_views(&mut self, remove: impl Fn(&View) -> bool) {
+		let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.views)
+			.into_iter()
+			.partition(remove);
+
+		self.views = kept;
+		removed.into_iter().for_each(View::destroy);
+	}
+
+	fn wants_output(&self, output: &Output) -> bool {
+		match &self.config.output {
+			Some(name) => output.name.as_ref() == Some(name),
+			None => true,
+		}
+	}
 
-		base_surface.commit();
-		base_surface.frame(queue_handle, ());
+	fn init_views(&mut self, queue_handle: &QueueHandle<Window>) {
+		self.layer = match (self.config.layer, self.layer_shell.as_ref()) {
+			(Some(layer), Some(_)) => Some(layer),
+			(Some(_), None) => {
+				eprintln!("Compositor does not support wlr-layer-shell, opening a window instead");
+				None
+			},
+			(None, _) => None,
+		};
+
+		if self.layer.is_none() {
+			self.add_view(None, queue_handle);
+		} else {
+			let outputs = self.outputs.iter()
+				.filter(|output| self.wants_output(output))
+				.map(|output| (output.output.clone(), output.name.clone()))
+				.collect::<Vec<_>>();
+
+			if let (Some(name), true) = (&self.config.output, outputs.is_empty()) {
+				let names = self.outputs.iter()
+					.filter_map(|output| output.name.as_deref())
+					.collect::<Vec<_>>();
+
+				eprintln!("No output named {:?} yet, available outputs are {:?}", name, names);
+			}
+
+			for output in outputs {
+				self.add_view(Some(output), queue_handle);
+			}
+		}
+
+		self.initialised = true;
+	}
+
+	/// Shows the visualiser on `output` as a layer, or in a window if there is
+	/// no output.
+	fn add_view(
+		&mut self,
+		output: Option<(WlOutput, Option<String>)>,
+		queue_handle: &QueueHandle<Window>,
+	) {
+		let compositor = self.compositor.as_ref()
+			.expect("Compositor does not support wl_compositor");
 
-		self.graphics_state = Some(GraphicsState {
-			surface: xdg_surface,
-			toplevel,
+		let surface = compositor.create_surface(queue_handle, ());
+
+		// an empty input region lets clicks through to whatever is below
+		let region = compositor.create_region(queue_handle, ());
+		surface.set_input_region(Some(&region));
+
+		let (output, output_name) = match output {
+			Some((output, name)) => (Some(output), name),
+			None => (None, None),
+		};
+
+		let role = match self.layer {
+			Some(layer) => Role::Layer(self.init_layer_surface(&surface, output.as_ref(), layer, queue_handle)),
+			None => self.init_xdg_surface(&surface, queue_handle),
+		};
+
+		let layout = layout::from_config(&self.config, output_name.as_deref())
+			.unwrap_or_else(|error| {
+				eprintln!("Failed to load layout for {}: {}", output_name.as_deref().unwrap_or("window"), error);
+				Shape::Fill.generate(0, 0.0, false)
+			});
+
+		let extent = [self.config.width, self.config.height];
+		let graphics = Graphics::new(&mut self.graphics_context, &self.display, &surface, &layout, extent, &self.config, &self.shaders);
+
+		let graphics = match graphics {
+			Ok(graphics) => graphics,
+			Err(error) => {
+				eprintln!("Can't show the visualiser on {}: {}", output_name.as_deref().unwrap_or("window"), error);
+
+				role.destroy();
+				surface.destroy();
+
+				// a window is the only view, so there would be nothing to show
+				if self.layer.is_none() {
+					self.running = false;
+				}
+
+				return;
+			},
+		};
+
+		surface.commit();
+		surface.frame(queue_handle, surface.id());
+
+		self.views.push(View {
+			output,
+			output_name,
+			surface,
+			role,
 			graphics,
+			configured: false,
+			pending_size: None,
 		});
 	}
+
+	fn init_xdg_surface(&self, surface: &WlSurface, queue_handle: &QueueHandle<Window>) -> Role {
+		let wm_base = self.wm_base.as_ref()
+			.expect("Compositor does not support xdg_wm_base");
+
+		let xdg_surface = wm_base.get_xdg_surface(surface, queue_handle, surface.id());
+		let toplevel = xdg_surface.get_toplevel(queue_handle, surface.id());
+		toplevel.set_title("Visualiser".into());
+		toplevel.set_app_id(env!("CARGO_PKG_NAME").into());
+
+		Role::Toplevel { surface: xdg_surface, toplevel }
+	}
+
+	fn init_layer_surface(
+		&self,
+		surface: &WlSurface,
+		output: Option<&WlOutput>,
+		layer: Layer,
+		queue_handle: &QueueHandle<Window>,
+	) -> ZwlrLayerSurfaceV1 {
+		let layer_surface = self.layer_shell.as_ref().unwrap().get_layer_surface(
+			surface,
+			output,
+			layer.into(),
+			env!("CARGO_PKG_NAME").into(),
+			queue_handle,
+			surface.id(),
+		);
+
+		let anchor = layer_shell::anchor(&self.config.anchor);
+		let [top, right, bottom, left] = layer_shell::margins(&self.config.margin);
+
+		// a size of 0 stretches the surface between opposite anchored edges
+		let width = if anchor.contains(Anchor::Left | Anchor::Right) {
+			0
+		} else {
+			self.config.width
+		};
+		let height = if anchor.contains(Anchor::Top | Anchor::Bottom) {
+			0
+		} else {
+			self.config.height
+		};
+
+		layer_surface.set_anchor(anchor);
+		layer_surface.set_size(width, height);
+		layer_surface.set_margin(top, right, bottom, left);
+		layer_surface.set_exclusive_zone(self.config.exclusive_zone);
+		layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+		layer_surface
+	}
+
+	/// Replaces a zero size from the compositor, which leaves that dimension
+	/// up to us, with the configured size.
+	fn size_or_default(&self, width: u32, height: u32) -> [u32; 2] {
+		let width = if width > 0 { width } else { self.config.width };
+		let height = if height > 0 { height } else { self.config.height };
+
+		[width.max(1), height.max(1)]
+	}
 }
 
 impl Dispatch<WlRegistry, ()> for Window {
@@ -102,27 +439,47 @@ impl Dispatch<WlRegistry, ()> for Window {
 			wl_registry::Event::Global { name, interface, version }
 			if interface.as_str() == "wl_compositor" => {
 				let compositor = registry.bind::<WlCompositor, _, _>(name, version, queue_handle, ());
-
-				let surface = compositor.create_surface(queue_handle, ());
-				
-				let region = compositor.create_region(queue_handle, ());
-				surface.set_input_region(Some(&region));
-
-				let previous_surface = state.base_surface.replace(surface);
-				assert!(previous_surface.is_none());
-
-				if state.wm_base.is_some() && state.graphics_state.is_none() {
-					state.init_xdg_surface(queue_handle);
-				}
+				let previous_compositor = state.compositor.replace(compositor);
+				assert!(previous_compositor.is_none());
 			},
 			wl_registry::Event::Global { name, interface, version }
 			if interface.as_str() == "xdg_wm_base" => {
 				let wm_base = registry.bind::<XdgWmBase, _, _>(name, version, queue_handle, ());
 				let previous_base = state.wm_base.replace(wm_base);
 				assert!(previous_base.is_none());
+			},
+			wl_registry::Event::Global { name, interface, version }
+			if interface.as_str() == "zwlr_layer_shell_v1" => {
+				let layer_shell = registry.bind::<ZwlrLayerShellV1, _, _>(
+					name,
+					version.min(LAYER_SHELL_VERSION),
+					queue_handle,
+					(),
+				);
+				state.layer_shell = Some(layer_shell);
+			},
+			wl_registry::Event::Global { name, interface, version }
+			if interface.as_str() == "wl_output" => {
+				let output = registry.bind::<WlOutput, _, _>(
+					name,
+					version.min(OUTPUT_VERSION),
+					queue_handle,
+					(),
+				);
+
+				// a view is added once the output has sent its name
+				state.outputs.push(Output { global: name, output, name: None, reopens: 0, last_closed: None });
+			},
+			wl_registry::Event::GlobalRemove { name } => {
+				let Some(index) = state.outputs.iter().position(|output| output.global == name) else {
+					return;
+				};
 
-				if state.base_surface.is_some() && state.graphics_state.is_none() {
-					state.init_xdg_surface(queue_handle);
+				let output = state.outputs.remove(index).output;
+				state.remove_views(|view| view.output.as_ref() == Some(&output));
+
+				if output.version() >= 3 {
+					output.release();
 				}
 			},
 			_ => {},
@@ -172,53 +529,47 @@ impl Dispatch<XdgWmBase, ()> for Window {
 	}
 }
 
-impl Dispatch<XdgSurface, ()> for Window {
+impl Dispatch<XdgSurface, ObjectId> for Window {
 	fn event(
-		_: &mut Self,
+		state: &mut Self,
 		surface: &XdgSurface,
 		event: xdg_surface::Event,
-		_: &(),
+		id: &ObjectId,
 		_: &Connection,
 		_: &QueueHandle<Self>,
 	) {
 		match event {
 			xdg_surface::Event::Configure { serial } => {
-				println!("configure_surface: {}", serial);
-				// TODO: actually configure
-				surface.send_request(xdg_surface::Request::AckConfigure { serial }).unwrap()
+				surface.send_request(xdg_surface::Request::AckConfigure { serial }).unwrap();
+
+				if let Some(view) = state.view_mut(id) {
+					let size = view.pending_size.take();
+					view.configure(size);
+				}
 			},
 			event => unimplemented!("xdg_wm_base unknown event: {:?}", event)
 		}
 	}
 }
 
-impl Dispatch<XdgToplevel, ()> for Window {
+impl Dispatch<XdgToplevel, ObjectId> for Window {
 	fn event(
 		state: &mut Self,
-		toplevel: &XdgToplevel,
+		_: &XdgToplevel,
 		event: xdg_toplevel::Event,
-		_: &(),
+		id: &ObjectId,
 		_: &Connection,
 		_: &QueueHandle<Self>,
 	) {
 		match event {
-			xdg_toplevel::Event::ConfigureBounds { width, height } => {
-				println!("max_size: {}×{}", width, height);
-			},
-			xdg_toplevel::Event::Configure { states, width, height }
-			if width == 0 && height == 0 => {
-				if !state.configured {
-					// TODO: do the configuring
-					state.graphics_state.as_mut().unwrap().graphics.draw(Some(Box::new([0.0; BUFFER_SIZE])));
-					println!("configure");
-					state.configured = true;
-				} else {
-					// TODO: do a reconfigure
+			// only a hint for the initial size, which is configured instead
+			xdg_toplevel::Event::ConfigureBounds { .. } => {},
+			xdg_toplevel::Event::Configure { states: _, width, height } => {
+				let size = state.size_or_default(width.max(0) as u32, height.max(0) as u32);
+
+				if let Some(view) = state.view_mut(id) {
+					view.pending_size = Some(size);
 				}
-				println!("self configure: {:?}", states);
-			},
-			xdg_toplevel::Event::Configure { states, width, height } => {
-				println!("configure: {}×{}, {:?}", width, height, states);
 			},
 			xdg_toplevel::Event::Close => {
 				state.running = false;
@@ -229,27 +580,26 @@ impl Dispatch<XdgToplevel, ()> for Window {
 	}
 }
 
-impl Dispatch<WlCallback, ()> for Window {
+impl Dispatch<WlCallback, ObjectId> for Window {
 	fn event(
 		state: &mut Self,
 		_: &WlCallback,
 		event: wl_callback::Event,
-		_: &(),
+		id: &ObjectId,
 		_: &Connection,
 		queue_handle: &QueueHandle<Self>,
 	) {
 		match event {
 			wl_callback::Event::Done { callback_data } => {
-				let surface = state.base_surface.as_ref().unwrap();
-				let interval = Duration::from_millis((callback_data - state.last_frame) as u64);
-				state.last_frame = callback_data;
-				
-				surface.frame(queue_handle, ());
+				state.advance(callback_data);
 
-				let data = state.visualiser.write().unwrap()
-					.fft_interval(interval);
+				let Some(view) = state.views.iter_mut().find(|view| &view.surface.id() == id) else {
+					// the view was removed while waiting for the frame
+					return;
+				};
 
-				state.graphics_state.as_mut().unwrap().graphics.draw(data);
+				view.surface.frame(queue_handle, id.clone());
+				view.draw(state.analyser.frame());
 			},
 			event => unimplemented!("wl_callback unknown event: {:?}", event)
 		}
@@ -268,3 +618,111 @@ impl Dispatch<WlRegion, ()> for Window {
 		unimplemented!("wl_region unknown event: {:?}", event)
 	}
 }
+
+impl Dispatch<WlOutput, ()> for Window {
+	fn event(
+		state: &mut Self,
+		output: &WlOutput,
+		event: wl_output::Event,
+		_: &(),
+		_: &Connection,
+		queue_handle: &QueueHandle<Self>,
+	) {
+		match event {
+			wl_output::Event::Name { name } => {
+				if let Some(known) = state.outputs.iter_mut().find(|known| &known.output == output) {
+					known.name = Some(name);
+				}
+			},
+			// sent once all the details of the output are known
+			wl_output::Event::Done if state.initialised && state.layer.is_some() => {
+				let has_view = state.views.iter().any(|view| view.output.as_ref() == Some(output));
+
+				let new_output = state.outputs.iter()
+					.find(|known| &known.output == output)
+					.filter(|known| !has_view && state.wants_output(known))
+					.map(|known| (known.output.clone(), known.name.clone()));
+
+				if new_output.is_some() {
+					state.add_view(new_output, queue_handle);
+				}
+			},
+			_ => {},
+		}
+	}
+}
+
+impl Dispatch<ZwlrLayerShellV1, ()> for Window {
+	fn event(
+		_: &mut Self,
+		_: &ZwlrLayerShellV1,
+		_: zwlr_layer_shell_v1::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		// the layer shell has no events in the version bound
+	}
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ObjectId> for Window {
+	fn event(
+		state: &mut Self,
+		layer_surface: &ZwlrLayerSurfaceV1,
+		event: zwlr_layer_surface_v1::Event,
+		id: &ObjectId,
+		_: &Connection,
+		queue_handle: &QueueHandle<Self>,
+	) {
+		match event {
+			zwlr_layer_surface_v1::Event::Configure { serial, width, height } => {
+				layer_surface.ack_configure(serial);
+
+				let size = state.size_or_default(width, height);
+
+				if let Some(view) = state.view_mut(id) {
+					view.configure(Some(size));
+				}
+			},
+			// the output went away or the compositor wants the layer gone
+			zwlr_layer_surface_v1::Event::Closed => {
+				// a layer which was shown is opened again on the same output,
+				// one closed before it was ever configured was refused so isn't
+				let output = state.views.iter()
+					.find(|view| &view.surface.id() == id && view.configured)
+					.and_then(|view| view.output.clone());
+
+				let reopen = output.and_then(|output| {
+					let known = state.outputs.iter_mut().find(|known| known.output == output)?;
+					let now = Instant::now();
+
+					if known.last_closed.map_or(true, |closed| now - closed > REOPEN_RESET) {
+						known.reopens = 0;
+					}
+
+					known.last_closed = Some(now);
+					known.reopens += 1;
+
+					if known.reopens > MAX_REOPENS {
+						eprintln!(
+							"The visualiser on {} keeps being closed, not opening it again",
+							known.name.as_deref().unwrap_or("an output"),
+						);
+						return None;
+					}
+
+					Some((output, known.name.clone()))
+				});
+
+				state.remove_views(|view| &view.surface.id() == id);
+
+				// otherwise another view is added if the output comes back
+				if reopen.is_some() {
+					state.add_view(reopen, queue_handle);
+				}
+			},
+			// from a newer version of the protocol than was bound
+			_ => {},
+		}
+	}
+}
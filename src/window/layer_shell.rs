@@ -0,0 +1,64 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1::Anchor};
+
+/// Layers of the desktop shell, from the bottom up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Layer {
+	/// Below everything, in place of the wallpaper
+	Background,
+	/// Above the wallpaper but below windows
+	Bottom,
+	/// Above windows but below fullscreen windows
+	Top,
+	/// Above everything
+	Overlay,
+}
+
+impl From<Layer> for zwlr_layer_shell_v1::Layer {
+	fn from(layer: Layer) -> Self {
+		match layer {
+			Layer::Background => zwlr_layer_shell_v1::Layer::Background,
+			Layer::Bottom => zwlr_layer_shell_v1::Layer::Bottom,
+			Layer::Top => zwlr_layer_shell_v1::Layer::Top,
+			Layer::Overlay => zwlr_layer_shell_v1::Layer::Overlay,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Edge {
+	Top,
+	Bottom,
+	Left,
+	Right,
+}
+
+impl Edge {
+	pub const ALL: [Edge; 4] = [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right];
+}
+
+pub fn anchor(edges: &[Edge]) -> Anchor {
+	edges.iter()
+		.map(|edge| match edge {
+			Edge::Top => Anchor::Top,
+			Edge::Bottom => Anchor::Bottom,
+			Edge::Left => Anchor::Left,
+			Edge::Right => Anchor::Right,
+		})
+		.fold(Anchor::empty(), |anchor, edge| anchor | edge)
+}
+
+/// Expands one to four values into top, right, bottom and left margins the
+/// same way as CSS.
+pub fn margins(values: &[i32]) -> [i32; 4] {
+	match *values {
+		[] => [0; 4],
+		[all] => [all; 4],
+		[vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+		[top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+		[top, right, bottom, left, ..] => [top, right, bottom, left],
+	}
+}
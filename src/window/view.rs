@@ -0,0 +1,75 @@
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_protocols::xdg::shell::client::xdg_surface::XdgSurface;
+use wayland_protocols::xdg::shell::client::xdg_toplevel::XdgToplevel;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::ZwlrLayerSurfaceV1;
+
+use crate::graphics::Graphics;
+use crate::visualiser::Frame;
+
+/// What the surface is shown as.
+pub(super) enum Role {
+	Toplevel {
+		surface: XdgSurface,
+		toplevel: XdgToplevel,
+	},
+	Layer(ZwlrLayerSurfaceV1),
+}
+
+impl Role {
+	pub fn destroy(self) {
+		match self {
+			Role::Toplevel { surface, toplevel } => {
+				toplevel.destroy();
+				surface.destroy();
+			},
+			Role::Layer(layer_surface) => layer_surface.destroy(),
+		}
+	}
+}
+
+/// A surface showing the visualiser, either as a window or on an output.
+pub(super) struct View {
+	/// the output a layer is shown on, if it was chosen by us
+	pub output: Option<WlOutput>,
+	/// used to pick the layout for the output
+	pub output_name: Option<String>,
+	pub surface: WlSurface,
+	pub role: Role,
+	pub graphics: Graphics,
+	pub configured: bool,
+	/// size from the latest toplevel configure, applied once the surface
+	/// configure which follows it is acknowledged
+	pub pending_size: Option<[u32; 2]>,
+}
+
+impl View {
+	/// Handles a configure once it has been acknowledged.
+	pub fn configure(&mut self, size: Option<[u32; 2]>) {
+		if let Some(size) = size {
+			self.graphics.resize(size);
+		}
+
+		if !self.configured {
+			// frame callbacks only start once something has been drawn
+			self.draw(&Frame::empty());
+			self.configured = true;
+		}
+	}
+
+	/// Draws `frame`, committing the surface anyway if nothing could be
+	/// presented so the frame callback already requested still comes.
+	pub fn draw(&mut self, frame: &Frame) {
+		if !self.graphics.draw(Some(frame)) {
+			self.surface.commit();
+		}
+	}
+
+	pub fn destroy(self) {
+		// the swapchain has to go before the surface it presents to
+		drop(self.graphics);
+
+		self.role.destroy();
+		self.surface.destroy();
+	}
+}